@@ -2,12 +2,109 @@
 #include <Adafruit_Sensor.h>
 #include <Wire.h>
 
+// see controller/protocol/src/lib.rs for the frame format, keep this in step with it
+#define PROTOCOL_VERSION 1
+
+// feedback from the game
+#define LED_PIN 2
+#define MOTOR_PIN 4
+#define FLASH_MS 150
+// a button to ground, the bell, sent as bit 0 of `btn`
+#define BELL_PIN 15
+#define BUTTON_BELL 1
+
 Adafruit_MPU6050 mpu;
 
 sensors_event_t a, g, temp;
+uint32_t seq = 0;
+char frame[160];
+
+char command[64];
+size_t commandLen = 0;
+bool ledOn = false;
+unsigned long pulseUntil = 0;
+// blinks left as led toggles, counting down
+int flashToggles = 0;
+unsigned long nextToggle = 0;
+
+// wraps the payload as `@<payload>*<checksum>` on its own line
+void sendFrame(const char *payload) {
+  uint8_t checksum = 0;
+  for (const char *c = payload; *c; c++) {
+    checksum ^= *c;
+  }
+  Serial.printf("@%s*%02X\n", payload, checksum);
+}
+
+// handles one `@<payload>*<checksum>` line from the game
+void handleCommand(char *line) {
+  if (line[0] != '@') {
+    return;
+  }
+  char *star = strrchr(line, '*');
+  if (star == NULL) {
+    return;
+  }
+  *star = '\0';
+  char *payload = line + 1;
+
+  uint8_t checksum = 0;
+  for (const char *c = payload; *c; c++) {
+    checksum ^= *c;
+  }
+  if (strtoul(star + 1, NULL, 16) != checksum) {
+    return;
+  }
+
+  unsigned long value;
+  if (sscanf(payload, "PULSE ms=%lu", &value) == 1) {
+    pulseUntil = millis() + value;
+    digitalWrite(MOTOR_PIN, HIGH);
+  } else if (sscanf(payload, "LED on=%lu", &value) == 1) {
+    ledOn = value != 0;
+    if (flashToggles == 0) {
+      digitalWrite(LED_PIN, ledOn ? HIGH : LOW);
+    }
+  } else if (sscanf(payload, "FLASH n=%lu", &value) == 1) {
+    flashToggles = value * 2;
+    nextToggle = millis();
+  }
+}
+
+void readCommands() {
+  while (Serial.available() > 0) {
+    char c = Serial.read();
+    if (c == '\n' || c == '\r') {
+      command[commandLen] = '\0';
+      if (commandLen > 0) {
+        handleCommand(command);
+      }
+      commandLen = 0;
+    } else if (commandLen < sizeof(command) - 1) {
+      command[commandLen++] = c;
+    }
+  }
+}
+
+void updateFeedback() {
+  unsigned long now = millis();
+  if (pulseUntil != 0 && (long)(now - pulseUntil) >= 0) {
+    digitalWrite(MOTOR_PIN, LOW);
+    pulseUntil = 0;
+  }
+  if (flashToggles > 0 && (long)(now - nextToggle) >= 0) {
+    flashToggles--;
+    // odd counts are lit, so it ends up off and then back to the status
+    digitalWrite(LED_PIN, flashToggles % 2 ? HIGH : (flashToggles == 0 && ledOn ? HIGH : LOW));
+    nextToggle = now + FLASH_MS;
+  }
+}
 
 void setup(void) {
   Serial.begin(115200);
+  pinMode(LED_PIN, OUTPUT);
+  pinMode(MOTOR_PIN, OUTPUT);
+  pinMode(BELL_PIN, INPUT_PULLUP);
 
   if (!mpu.begin()) {
     Serial.println("Failed to find MPU6050 chip");
@@ -19,10 +116,15 @@ void setup(void) {
   mpu.setGyroRange(MPU6050_RANGE_250_DEG);
 
   Serial.println("");
+  snprintf(frame, sizeof(frame), "HELLO proto=%d dev=arduino-esp32", PROTOCOL_VERSION);
+  sendFrame(frame);
   delay(100);
 }
 
 void loop() {
+  readCommands();
+  updateFeedback();
+
   mpu.getEvent(&a, &g, &temp);
 
   // Serial.print("AccelX:");
@@ -38,11 +140,22 @@ void loop() {
   // Serial.print(g.gyro.x);
   // Serial.print(",");
   //Serial.print("GyroY:");
-  Serial.println(g.gyro.y);
+  //Serial.println(g.gyro.y);
   // Serial.print(",");
   // Serial.print("GyroZ:");
   // Serial.print(g.gyro.z);
   // Serial.println("");
 
+  int len = snprintf(frame, sizeof(frame),
+    "S seq=%lu t=%lu gx=%.3f gy=%.3f gz=%.3f ax=%.3f ay=%.3f az=%.3f",
+    (unsigned long)seq++, (unsigned long)millis(),
+    g.gyro.x, g.gyro.y, g.gyro.z,
+    a.acceleration.x, a.acceleration.y, a.acceleration.z);
+  // pressed reads low, and no buttons held means no `btn` at all
+  if (digitalRead(BELL_PIN) == LOW) {
+    snprintf(frame + len, sizeof(frame) - len, " btn=%d", BUTTON_BELL);
+  }
+  sendFrame(frame);
+
   delay(100);
 }
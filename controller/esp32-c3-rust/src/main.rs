@@ -1,12 +1,166 @@
+use std::{
+	error::Error,
+	io::BufRead,
+	net::UdpSocket,
+	sync::mpsc,
+	thread,
+	time::{Duration, Instant},
+};
+
+use controller_protocol::{parse_frame, Command, Frame, BUTTON_BELL};
+use embedded_svc::wifi::{ClientConfiguration, Configuration};
 use esp_idf_hal::{
-	delay::FreeRtos,
+	gpio::{Gpio6, Gpio7, Gpio9, Input, Output, PinDriver, Pull},
 	i2c::{I2cConfig, I2cDriver},
+	modem::Modem,
 	prelude::*,
 };
-use esp_idf_sys as _;
-use icm42670::{accelerometer::vector::F32x3, prelude::_accelerometer_Accelerometer, Address};
+use esp_idf_svc::{
+	eventloop::EspSystemEventLoop,
+	nvs::EspDefaultNvsPartition,
+	timer::EspTaskTimerService,
+	wifi::{BlockingWifi, EspWifi},
+};
+use esp_idf_sys::{self as _, EspError};
+use firmware_core::{Config, Controller, Feedback, FeedbackPins, FilterKind, Imu, Reading, Sink, Tick};
+use icm42670::{accelerometer::vector::F32x3, prelude::_accelerometer_Accelerometer, Address, Icm42670};
+use shared_bus::{BusManagerSimple, I2cProxy, NullMutex};
 //use shtcx::{Measurement, PowerMode::*};
 
+const STANDARD_GRAVITY: f32 = 9.80665;
+// how long to leave a sensor that won't set up alone before trying again
+const INIT_BACKOFF: Duration = Duration::from_millis(500);
+// the game hears the rate in the hello, a median filter instead drops
+// spikes without smoothing, and send_roll has the roll worked out here
+const CONFIG: Config = Config {
+	rate_hz: 50,
+	filter: FilterKind::LowPass { cutoff_hz: 10.0 },
+	send_roll: false,
+};
+// set these when building to stream over wi-fi instead of usb serial, e.g.
+// CONTROLLER_WIFI_SSID=park CONTROLLER_WIFI_PASSWORD=... CONTROLLER_UDP_TARGET=192.168.1.20:4210
+const WIFI_SSID: Option<&str> = option_env!("CONTROLLER_WIFI_SSID");
+const WIFI_PASSWORD: &str = match option_env!("CONTROLLER_WIFI_PASSWORD") {
+	Some(password) => password,
+	None => "",
+};
+const UDP_TARGET: Option<&str> = option_env!("CONTROLLER_UDP_TARGET");
+
+type Bus<'d> = BusManagerSimple<I2cDriver<'d>>;
+type Driver<'b, 'd> = Icm42670<I2cProxy<'b, NullMutex<I2cDriver<'d>>>>;
+
+#[derive(Debug)]
+enum IcmError {
+	NotSetUp,
+	Sensor(icm42670::Error<EspError>),
+}
+
+/// The ICM42670 on the board's i2c bus.
+struct Icm<'b, 'd> {
+	bus: &'b Bus<'d>,
+	driver: Option<Driver<'b, 'd>>,
+}
+
+impl Imu for Icm<'_, '_> {
+	type Error = IcmError;
+
+	fn init(&mut self) -> Result<(), IcmError> {
+		self.driver = None;
+		let driver = Icm42670::new(self.bus.acquire_i2c(), Address::Primary).map_err(IcmError::Sensor)?;
+		self.driver = Some(driver);
+		Ok(())
+	}
+
+	fn read(&mut self) -> Result<Reading, IcmError> {
+		let driver = self.driver.as_mut().ok_or(IcmError::NotSetUp)?;
+
+		// the driver reports g, the protocol wants m/s^2
+		let F32x3 { x: ax, y: ay, z: az } = driver.accel_norm().map_err(IcmError::Sensor)?;
+		// the driver reports deg/s, the protocol wants rad/s
+		let F32x3 { x: gx, y: gy, z: gz } = driver.gyro_norm().map_err(IcmError::Sensor)?;
+
+		Ok(Reading {
+			gyro: [gx.to_radians(), gy.to_radians(), gz.to_radians()],
+			accel: [ax * STANDARD_GRAVITY, ay * STANDARD_GRAVITY, az * STANDARD_GRAVITY],
+		})
+	}
+}
+
+/// Frames go out one per line, over usb serial or to the game over udp.
+struct Link {
+	udp: Option<UdpSocket>,
+}
+
+impl Sink for Link {
+	fn send(&mut self, frame: &Frame) {
+		let mut line = String::new();
+		// writing to a String can't fail
+		let _ = frame.write(&mut line);
+		match &self.udp {
+			Some(socket) => {
+				line.push('\n');
+				// a lost datagram is a skipped seq, the game copes with those
+				let _ = socket.send(line.as_bytes());
+			},
+			None => println!("{}", line),
+		}
+	}
+}
+
+/// The red led on the board, and a vibration motor on a free pin.
+struct Pins<'d> {
+	led: PinDriver<'d, Gpio7, Output>,
+	motor: PinDriver<'d, Gpio6, Output>,
+}
+
+impl FeedbackPins for Pins<'_> {
+	fn set_led(&mut self, on: bool) {
+		self.led.set_level(on.into()).ok();
+	}
+
+	fn set_motor(&mut self, on: bool) {
+		self.motor.set_level(on.into()).ok();
+	}
+}
+
+/// The bell, the board's boot button or one wired from GPIO9 to ground.
+struct Bell<'d> {
+	pin: PinDriver<'d, Gpio9, Input>,
+}
+
+impl Bell<'_> {
+	fn buttons(&self) -> u8 {
+		// pulled up, so pressed reads low
+		if self.pin.is_low() { BUTTON_BELL } else { 0 }
+	}
+}
+
+fn connect_udp(modem: Modem, ssid: &str, target: &str) -> Result<(BlockingWifi<EspWifi<'static>>, UdpSocket), Box<dyn Error>> {
+	let sys_loop = EspSystemEventLoop::take()?;
+	let nvs = EspDefaultNvsPartition::take()?;
+	let mut wifi = BlockingWifi::wrap(EspWifi::new(modem, sys_loop.clone(), Some(nvs))?, sys_loop)?;
+	wifi.set_configuration(&Configuration::Client(ClientConfiguration {
+		ssid: ssid.into(),
+		password: WIFI_PASSWORD.into(),
+		..Default::default()
+	}))?;
+	wifi.start()?;
+	wifi.connect()?;
+	wifi.wait_netif_up()?;
+
+	let socket = UdpSocket::bind("0.0.0.0:0")?;
+	socket.connect(target)?;
+	Ok((wifi, socket))
+}
+
+// anything that doesn't check out is dropped, false once the main loop is gone
+fn forward_command(line: &str, commands: &mpsc::Sender<Command>) -> bool {
+	match parse_frame(line.trim()) {
+		Ok(Frame::Command(command)) => commands.send(command).is_ok(),
+		_ => true,
+	}
+}
+
 fn main() {
 	esp_idf_sys::link_patches();
 
@@ -17,6 +171,7 @@ fn main() {
 		.sda_enable_pullup(true)
 		.scl_enable_pullup(true);
 
+	// without i2c or the pins there's nothing to retry, these can stay fatal
 	let shared_bus = shared_bus::BusManagerSimple::new(
 		I2cDriver::new(
 			peripherals.i2c0,
@@ -27,36 +182,84 @@ fn main() {
 		.expect("Failed to create i2c driver"),
 	);
 
+	let mut pins = Pins {
+		led: PinDriver::output(peripherals.pins.gpio7).expect("Failed to set up the led"),
+		motor: PinDriver::output(peripherals.pins.gpio6).expect("Failed to set up the motor"),
+	};
+
+	let mut bell_pin = PinDriver::input(peripherals.pins.gpio9).expect("Failed to set up the bell");
+	bell_pin.set_pull(Pull::Up).expect("Failed to pull up the bell");
+	let bell = Bell { pin: bell_pin };
+
+	// without wi-fi, or if it doesn't come up, usb serial still works
+	let (_wifi, udp) = match (WIFI_SSID, UDP_TARGET) {
+		(Some(ssid), Some(target)) => match connect_udp(peripherals.modem, ssid, target) {
+			Ok((wifi, socket)) => (Some(wifi), Some(socket)),
+			Err(e) => {
+				eprintln!("streaming to {} over wi-fi failed, using usb serial: {}", target, e);
+				(None, None)
+			},
+		},
+		_ => (None, None),
+	};
+
+	// reading blocks, so commands come in on their own threads
+	let (commands, received) = mpsc::channel();
+	if let Some(socket) = udp.as_ref().and_then(|socket| socket.try_clone().ok()) {
+		let commands = commands.clone();
+		thread::spawn(move || {
+			let mut buffer = [0u8; 512];
+			while let Ok(n) = socket.recv(&mut buffer) {
+				for line in String::from_utf8_lossy(&buffer[..n]).lines() {
+					if !forward_command(line, &commands) {
+						return;
+					}
+				}
+			}
+		});
+	}
+	thread::spawn(move || {
+		for line in std::io::stdin().lock().lines().map_while(Result::ok) {
+			if !forward_command(&line, &commands) {
+				return;
+			}
+		}
+	});
+
+	let imu = Icm { bus: &shared_bus, driver: None };
+	let mut controller = Controller::new(imu, Link { udp }, "esp32-c3-rust", CONFIG);
+	let mut feedback = Feedback::default();
+	controller.start();
+
+	// a timer paces the samples so they come at the rate the hello promised,
+	// however long a read takes
+	let (tick, ticks) = mpsc::sync_channel(1);
+	let timer_service = EspTaskTimerService::new().expect("Failed to start the timer service");
+	let timer = timer_service
+		.timer(move || {
+			// a tick still waiting means the loop fell behind, don't pile more up
+			let _ = tick.try_send(());
+		})
+		.expect("Failed to create the sample timer");
+	timer.every(CONFIG.interval()).expect("Failed to start the sample timer");
+
+	let started = Instant::now();
+	for () in ticks.iter() {
+		let now_ms = started.elapsed().as_millis() as u32;
+		for command in received.try_iter() {
+			feedback.handle(command, now_ms, &mut pins);
+		}
+		feedback.update(now_ms, &mut pins);
 
-	let mut icm42670 = icm42670::Icm42670::new(shared_bus.acquire_i2c(), Address::Primary)
-		.expect("Failed to instantiate icm42670");
-
-	loop {
-
-		let temp = icm42670
-			.temperature()
-			.expect("Failed to read ICM42670 temp");
-
-		// let F32x3 {
-		// 	x: ax,
-		// 	y: ay,
-		// 	z: az,
-		// } = icm42670
-		// 	.accel_norm()
-		// 	.expect("Failed to read ICM42670 accel data");
-
-		let F32x3 {
-			x: gx,
-			y: gy,
-			z: gz,
-		} = icm42670
-			.gyro_norm()
-			.expect("Failed to read ICM42670 gyro data");
-
-		//println!(
-		//	"ICM42670:\n\t- temp: {}\n\t- accel: {}, {}, {}\n\t- gyro: {}, {}, {}",
-		//	temp, ax, ay, az, gx, gy, gz
-	    //);
-            println!("{}", gy);
+		controller.set_buttons(bell.buttons());
+		match controller.tick(now_ms) {
+			Tick::Sent(_) => {},
+			// the log goes to the same serial port, the game skips lines it can't parse
+			Tick::ReadFailed(e) => eprintln!("reading the ICM42670 failed: {:?}", e),
+			Tick::InitFailed(e) => {
+				eprintln!("setting up the ICM42670 failed: {:?}", e);
+				thread::sleep(INIT_BACKOFF);
+			},
+		}
 	}
 }
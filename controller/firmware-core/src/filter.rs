@@ -0,0 +1,97 @@
+use crate::Reading;
+
+/// How many readings the median filter looks at, odd so there's a middle one.
+pub const MEDIAN_WINDOW: usize = 3;
+
+/// How readings are smoothed before they go out.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FilterKind {
+    None,
+    /// first order low-pass, takes the edge off vibration from the road
+    LowPass { cutoff_hz: f32 },
+    /// median of the last few readings, drops single spikes without lag on steady ones
+    Median,
+}
+
+/// Smooths every channel of a reading on its own.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Filter {
+    kind: FilterKind,
+    // how much of each new reading the low-pass takes in
+    alpha: f32,
+    last: Option<[f32; 6]>,
+    history: [[f32; 6]; MEDIAN_WINDOW],
+    len: usize,
+    next: usize,
+}
+
+impl Filter {
+    /// Readings are expected `rate_hz` times a second.
+    pub fn new(kind: FilterKind, rate_hz: u32) -> Self {
+        let alpha = match kind {
+            FilterKind::LowPass { cutoff_hz } if cutoff_hz > 0.0 => {
+                let dt = 1.0 / rate_hz.max(1) as f32;
+                let rc = 1.0 / (2.0 * core::f32::consts::PI * cutoff_hz);
+                dt / (rc + dt)
+            },
+            _ => 1.0,
+        };
+        Self {
+            kind,
+            alpha,
+            last: None,
+            history: [[0.0; 6]; MEDIAN_WINDOW],
+            len: 0,
+            next: 0,
+        }
+    }
+
+    pub fn apply(&mut self, reading: Reading) -> Reading {
+        let channels = channels(&reading);
+        let filtered = match self.kind {
+            FilterKind::None => channels,
+            FilterKind::LowPass { .. } => {
+                let mut smoothed = self.last.unwrap_or(channels);
+                for (smoothed, new) in smoothed.iter_mut().zip(channels) {
+                    *smoothed += self.alpha * (new - *smoothed);
+                }
+                smoothed
+            },
+            FilterKind::Median => {
+                self.history[self.next] = channels;
+                self.next = (self.next + 1) % MEDIAN_WINDOW;
+                self.len = (self.len + 1).min(MEDIAN_WINDOW);
+                let mut median = [0.0; 6];
+                for (channel, median) in median.iter_mut().enumerate() {
+                    let mut values = [0.0; MEDIAN_WINDOW];
+                    for (value, past) in values.iter_mut().zip(&self.history[..self.len]) {
+                        *value = past[channel];
+                    }
+                    let values = &mut values[..self.len];
+                    values.sort_unstable_by(f32::total_cmp);
+                    // until the window fills up, the middle two of an even count
+                    *median = (values[(self.len - 1) / 2] + values[self.len / 2]) / 2.0;
+                }
+                median
+            },
+        };
+        self.last = Some(filtered);
+        Reading {
+            gyro: [filtered[0], filtered[1], filtered[2]],
+            accel: [filtered[3], filtered[4], filtered[5]],
+        }
+    }
+
+    /// Forgets past readings, after the sensor was set up again.
+    pub fn reset(&mut self) {
+        self.last = None;
+        self.len = 0;
+        self.next = 0;
+    }
+}
+
+fn channels(reading: &Reading) -> [f32; 6] {
+    let [gx, gy, gz] = reading.gyro;
+    let [ax, ay, az] = reading.accel;
+    [gx, gy, gz, ax, ay, az]
+}
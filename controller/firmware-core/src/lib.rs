@@ -0,0 +1,253 @@
+//! The controller's logic behind traits, so it runs the same on the esp32
+//! and in tests on the host. Time is passed in as milliseconds since start,
+//! wrapping like `millis()` does on the arduino.
+#![no_std]
+
+use core::{fmt::Debug, time::Duration};
+
+use controller_protocol::{Command, Frame, Hello, Sample, PROTOCOL_VERSION};
+
+mod filter;
+mod roll;
+
+pub use filter::*;
+pub use roll::*;
+
+/// A failed read is tried this many more times before the tick counts as failed.
+pub const READ_RETRIES: u32 = 2;
+/// After this many failed ticks in a row the sensor is set up again.
+pub const FAILED_TICKS_BEFORE_REINIT: u32 = 5;
+pub const FLASH_INTERVAL_MS: u32 = 150;
+
+/// One reading in the units the protocol uses.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Reading {
+    /// angular velocity in rad/s
+    pub gyro: [f32; 3],
+    /// acceleration in m/s^2
+    pub accel: [f32; 3],
+}
+
+pub trait Imu {
+    type Error: Debug;
+
+    /// Sets the sensor up, again after it stopped answering.
+    fn init(&mut self) -> Result<(), Self::Error>;
+    fn read(&mut self) -> Result<Reading, Self::Error>;
+}
+
+/// Where frames for the game go, usb serial on the esp32.
+pub trait Sink {
+    fn send(&mut self, frame: &Frame);
+}
+
+pub trait FeedbackPins {
+    fn set_led(&mut self, on: bool);
+    fn set_motor(&mut self, on: bool);
+}
+
+/// How the controller samples, fixed when it's built.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Config {
+    /// ticks a second, told to the game in the hello
+    pub rate_hz: u32,
+    pub filter: FilterKind,
+    /// work out the roll here and send it with every sample
+    pub send_roll: bool,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            rate_hz: 50,
+            filter: FilterKind::LowPass { cutoff_hz: 10.0 },
+            send_roll: false,
+        }
+    }
+}
+
+impl Config {
+    /// How long between ticks.
+    pub fn interval(&self) -> Duration {
+        Duration::from_micros(1_000_000 / self.rate_hz.max(1) as u64)
+    }
+}
+
+/// What happened in one tick.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Tick<E> {
+    Sent(Sample),
+    /// every try failed, the last error
+    ReadFailed(E),
+    /// the sensor is gone and didn't come back yet
+    InitFailed(E),
+}
+
+pub struct Controller<I, S> {
+    imu: I,
+    sink: S,
+    device: &'static str,
+    config: Config,
+    filter: Filter,
+    roll: RollEstimator,
+    last_sent_ms: Option<u32>,
+    seq: u32,
+    buttons: u8,
+    failed_ticks: u32,
+    needs_init: bool,
+}
+
+impl<I: Imu, S: Sink> Controller<I, S> {
+    /// The sensor is set up on the first tick. `tick` is meant to be
+    /// called every `config.interval()`, the controller doesn't keep time.
+    pub fn new(imu: I, sink: S, device: &'static str, config: Config) -> Self {
+        Self {
+            imu,
+            sink,
+            device,
+            config,
+            filter: Filter::new(config.filter, config.rate_hz),
+            roll: RollEstimator::default(),
+            last_sent_ms: None,
+            seq: 0,
+            buttons: 0,
+            failed_ticks: 0,
+            needs_init: true,
+        }
+    }
+
+    /// Says hello, the game resets its sequence tracking on it.
+    pub fn start(&mut self) {
+        self.sink.send(&Frame::Hello(Hello {
+            version: PROTOCOL_VERSION,
+            device: self.device,
+            rate_hz: Some(self.config.rate_hz),
+        }));
+    }
+
+    pub fn tick(&mut self, now_ms: u32) -> Tick<I::Error> {
+        if self.needs_init {
+            if let Err(e) = self.imu.init() {
+                return Tick::InitFailed(e);
+            }
+            self.needs_init = false;
+            self.failed_ticks = 0;
+            // whatever the sensor said before it went away is stale
+            self.filter.reset();
+            self.roll.reset();
+            self.last_sent_ms = None;
+        }
+
+        let mut result = self.imu.read();
+        for _ in 0..READ_RETRIES {
+            if result.is_ok() {
+                break;
+            }
+            result = self.imu.read();
+        }
+
+        match result {
+            Ok(reading) => {
+                self.failed_ticks = 0;
+                let reading = self.filter.apply(reading);
+                let roll = self.config.send_roll.then(|| {
+                    let dt = match self.last_sent_ms {
+                        Some(last) => now_ms.wrapping_sub(last) as f32 / 1000.0,
+                        None => 1.0 / self.config.rate_hz.max(1) as f32,
+                    };
+                    self.roll.update(&reading, dt)
+                });
+                self.last_sent_ms = Some(now_ms);
+                let sample = Sample {
+                    seq: Some(self.seq),
+                    timestamp_ms: Some(now_ms),
+                    gyro: reading.gyro,
+                    accel: Some(reading.accel),
+                    buttons: self.buttons,
+                    roll,
+                    cadence_rpm: None,
+                };
+                self.sink.send(&Frame::Sample(sample));
+                self.seq = self.seq.wrapping_add(1);
+                Tick::Sent(sample)
+            },
+            Err(e) => {
+                self.failed_ticks += 1;
+                if self.failed_ticks >= FAILED_TICKS_BEFORE_REINIT {
+                    self.needs_init = true;
+                }
+                Tick::ReadFailed(e)
+            },
+        }
+    }
+
+    /// Which buttons are held down, as the `BUTTON_*` bits. They go
+    /// out with every sample until they're set again.
+    pub fn set_buttons(&mut self, buttons: u8) {
+        self.buttons = buttons;
+    }
+
+    pub fn imu(&self) -> &I {
+        &self.imu
+    }
+
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    pub fn sink(&self) -> &S {
+        &self.sink
+    }
+}
+
+/// Runs the vibration motor and status led from the game's commands.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Feedback {
+    led_on: bool,
+    pulse_until: Option<u32>,
+    // led toggles left of a flash, and when the next one is due
+    flash_toggles: u32,
+    next_toggle: u32,
+}
+
+impl Feedback {
+    pub fn handle(&mut self, command: Command, now_ms: u32, pins: &mut impl FeedbackPins) {
+        match command {
+            Command::Pulse { millis } => {
+                self.pulse_until = Some(now_ms.wrapping_add(millis));
+                pins.set_motor(true);
+            },
+            Command::Led { on } => {
+                self.led_on = on;
+                // a flash puts it back when it's done
+                if self.flash_toggles == 0 {
+                    pins.set_led(on);
+                }
+            },
+            Command::Flash { times } => {
+                self.flash_toggles = times.saturating_mul(2);
+                self.next_toggle = now_ms;
+            },
+        }
+        self.update(now_ms, pins);
+    }
+
+    pub fn update(&mut self, now_ms: u32, pins: &mut impl FeedbackPins) {
+        if self.pulse_until.is_some_and(|until| reached(now_ms, until)) {
+            pins.set_motor(false);
+            self.pulse_until = None;
+        }
+        if self.flash_toggles > 0 && reached(now_ms, self.next_toggle) {
+            self.flash_toggles -= 1;
+            // odd counts are lit, and the last one goes back to the status
+            let lit = self.flash_toggles % 2 == 1 || (self.flash_toggles == 0 && self.led_on);
+            pins.set_led(lit);
+            self.next_toggle = now_ms.wrapping_add(FLASH_INTERVAL_MS);
+        }
+    }
+}
+
+// survives the clock wrapping after 49 days
+fn reached(now_ms: u32, deadline: u32) -> bool {
+    now_ms.wrapping_sub(deadline) < u32::MAX / 2
+}
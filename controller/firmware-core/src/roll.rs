@@ -0,0 +1,32 @@
+use crate::Reading;
+
+/// Seconds the gyro is trusted over the accelerometer, the same as the game's.
+pub const ROLL_TIME_CONSTANT: f32 = 0.5;
+
+/// Integrates gyro y into a roll angle, pulled towards where gravity
+/// says it is so it doesn't drift.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct RollEstimator {
+    roll: Option<f32>,
+}
+
+impl RollEstimator {
+    /// The roll in radians after `dt` seconds more of turning.
+    pub fn update(&mut self, reading: &Reading, dt: f32) -> f32 {
+        // the handlebar turns about y, so gravity moves between z and x
+        let accel_roll = libm::atan2f(-reading.accel[0], reading.accel[2]);
+        let roll = match self.roll {
+            Some(roll) => {
+                let alpha = ROLL_TIME_CONSTANT / (ROLL_TIME_CONSTANT + dt.max(0.0));
+                alpha * (roll + reading.gyro[1] * dt) + (1.0 - alpha) * accel_roll
+            },
+            None => accel_roll,
+        };
+        self.roll = Some(roll);
+        roll
+    }
+
+    pub fn reset(&mut self) {
+        self.roll = None;
+    }
+}
@@ -0,0 +1,294 @@
+use std::collections::VecDeque;
+
+use controller_protocol::{Command, Frame, Hello, BUTTON_BELL, PROTOCOL_VERSION};
+use firmware_core::*;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct SensorGone;
+
+/// Hands out scripted readings, and a still handlebar once they run out.
+#[derive(Default)]
+struct MockImu {
+    reads: VecDeque<Result<Reading, SensorGone>>,
+    inits: VecDeque<Result<(), SensorGone>>,
+    init_calls: usize,
+    read_calls: usize,
+}
+
+impl Imu for MockImu {
+    type Error = SensorGone;
+
+    fn init(&mut self) -> Result<(), SensorGone> {
+        self.init_calls += 1;
+        self.inits.pop_front().unwrap_or(Ok(()))
+    }
+
+    fn read(&mut self) -> Result<Reading, SensorGone> {
+        self.read_calls += 1;
+        self.reads.pop_front().unwrap_or(Ok(still()))
+    }
+}
+
+#[derive(Default)]
+struct Lines(Vec<String>);
+
+impl Sink for Lines {
+    fn send(&mut self, frame: &Frame) {
+        let mut line = String::new();
+        frame.write(&mut line).unwrap();
+        self.0.push(line);
+    }
+}
+
+#[derive(Default, Debug, PartialEq)]
+struct Pins {
+    led: bool,
+    motor: bool,
+    led_changes: usize,
+}
+
+impl FeedbackPins for Pins {
+    fn set_led(&mut self, on: bool) {
+        self.led = on;
+        self.led_changes += 1;
+    }
+
+    fn set_motor(&mut self, on: bool) {
+        self.motor = on;
+    }
+}
+
+fn still() -> Reading {
+    Reading { gyro: [0.0; 3], accel: [0.0, 0.0, 9.81] }
+}
+
+fn tilting() -> Reading {
+    Reading { gyro: [0.0, 0.5, 0.0], accel: [-3.0, 0.0, 9.3] }
+}
+
+// unfiltered, so readings come out as they went in
+const RAW: Config = Config { rate_hz: 10, filter: FilterKind::None, send_roll: false };
+
+fn controller(reads: Vec<Result<Reading, SensorGone>>) -> Controller<MockImu, Lines> {
+    with_config(reads, RAW)
+}
+
+fn with_config(reads: Vec<Result<Reading, SensorGone>>, config: Config) -> Controller<MockImu, Lines> {
+    let imu = MockImu { reads: reads.into(), ..Default::default() };
+    Controller::new(imu, Lines::default(), "mock", config)
+}
+
+// turning on a level handlebar
+fn spin(rate: f32) -> Reading {
+    Reading { gyro: [0.0, rate, 0.0], ..still() }
+}
+
+#[test]
+fn says_hello_first() {
+    let mut controller = controller(vec![]);
+    controller.start();
+
+    let mut expected = String::new();
+    Frame::Hello(Hello { version: PROTOCOL_VERSION, device: "mock", rate_hz: Some(10) }).write(&mut expected).unwrap();
+    assert_eq!(controller.sink().0, vec![expected]);
+}
+
+#[test]
+fn sends_readings_with_increasing_seq() {
+    let mut controller = controller(vec![Ok(still()), Ok(tilting())]);
+
+    let Tick::Sent(first) = controller.tick(0) else { panic!("nothing sent") };
+    let Tick::Sent(second) = controller.tick(100) else { panic!("nothing sent") };
+
+    assert_eq!((first.seq, first.timestamp_ms), (Some(0), Some(0)));
+    assert_eq!((second.seq, second.timestamp_ms), (Some(1), Some(100)));
+    assert_eq!(second.gyro, tilting().gyro);
+    assert_eq!(second.accel, Some(tilting().accel));
+    assert_eq!(controller.sink().0.len(), 2);
+    assert_eq!(controller.imu().init_calls, 1);
+}
+
+#[test]
+fn retries_a_failed_read_in_the_same_tick() {
+    let mut controller = controller(vec![Err(SensorGone), Err(SensorGone), Ok(tilting())]);
+
+    assert!(matches!(controller.tick(0), Tick::Sent(sample) if sample.gyro == tilting().gyro));
+    assert_eq!(controller.imu().read_calls, 3);
+}
+
+#[test]
+fn skips_the_tick_when_every_try_fails() {
+    let reads = vec![Err(SensorGone); READ_RETRIES as usize + 1];
+    let mut controller = controller(reads);
+
+    assert_eq!(controller.tick(0), Tick::ReadFailed(SensorGone));
+    assert!(controller.sink().0.is_empty());
+
+    // the seq doesn't skip, nothing was sent
+    assert!(matches!(controller.tick(100), Tick::Sent(sample) if sample.seq == Some(0)));
+}
+
+#[test]
+fn sets_the_sensor_up_again_after_failing_for_a_while() {
+    let failing_ticks = FAILED_TICKS_BEFORE_REINIT as usize;
+    let reads = vec![Err(SensorGone); failing_ticks * (READ_RETRIES as usize + 1)];
+    let mut controller = controller(reads);
+
+    for tick in 0..failing_ticks {
+        assert_eq!(controller.tick(tick as u32), Tick::ReadFailed(SensorGone));
+    }
+    assert_eq!(controller.imu().init_calls, 1);
+
+    assert!(matches!(controller.tick(1000), Tick::Sent(_)));
+    assert_eq!(controller.imu().init_calls, 2);
+}
+
+#[test]
+fn keeps_trying_to_set_up_a_missing_sensor() {
+    let mut missing = MockImu { inits: vec![Err(SensorGone), Err(SensorGone)].into(), ..Default::default() };
+    missing.reads.push_back(Ok(tilting()));
+    let mut controller = Controller::new(missing, Lines::default(), "mock", RAW);
+
+    assert_eq!(controller.tick(0), Tick::InitFailed(SensorGone));
+    assert_eq!(controller.tick(100), Tick::InitFailed(SensorGone));
+    assert!(matches!(controller.tick(200), Tick::Sent(sample) if sample.seq == Some(0)));
+    assert_eq!(controller.imu().init_calls, 3);
+    assert_eq!(controller.imu().read_calls, 1);
+}
+
+#[test]
+fn interval_follows_the_rate() {
+    assert_eq!(RAW.interval(), std::time::Duration::from_millis(100));
+    assert_eq!(Config::default().interval(), std::time::Duration::from_millis(20));
+}
+
+#[test]
+fn low_pass_smooths_a_step() {
+    let config = Config { filter: FilterKind::LowPass { cutoff_hz: 1.0 }, ..RAW };
+    let mut controller = with_config(vec![Ok(still()), Ok(spin(1.0)), Ok(spin(1.0))], config);
+
+    let rates: Vec<f32> = (0..3)
+        .map(|tick| match controller.tick(tick * 100) {
+            Tick::Sent(sample) => sample.gyro[1],
+            tick => panic!("nothing sent: {:?}", tick),
+        })
+        .collect();
+
+    assert_eq!(rates[0], 0.0);
+    assert!(0.0 < rates[1] && rates[1] < rates[2] && rates[2] < 1.0, "{:?}", rates);
+}
+
+#[test]
+fn median_drops_a_spike() {
+    let config = Config { filter: FilterKind::Median, ..RAW };
+    let reads = [0.1, 0.1, 5.0, 0.1, 0.2].map(|rate| Ok(spin(rate))).to_vec();
+    let mut controller = with_config(reads, config);
+
+    let rates: Vec<f32> = (0..5)
+        .map(|tick| match controller.tick(tick * 100) {
+            Tick::Sent(sample) => sample.gyro[1],
+            tick => panic!("nothing sent: {:?}", tick),
+        })
+        .collect();
+
+    assert!(rates.iter().all(|rate| *rate < 0.25), "{:?}", rates);
+}
+
+#[test]
+fn sends_the_buttons_while_held() {
+    let mut controller = controller(vec![]);
+    assert!(matches!(controller.tick(0), Tick::Sent(sample) if sample.buttons == 0));
+
+    controller.set_buttons(BUTTON_BELL);
+    assert!(matches!(controller.tick(100), Tick::Sent(sample) if sample.buttons == BUTTON_BELL));
+    assert!(matches!(controller.tick(200), Tick::Sent(sample) if sample.buttons == BUTTON_BELL));
+    assert!(controller.sink().0[1].contains(" btn=1*"));
+
+    controller.set_buttons(0);
+    assert!(matches!(controller.tick(300), Tick::Sent(sample) if sample.buttons == 0));
+}
+
+#[test]
+fn only_sends_roll_when_asked() {
+    let mut controller = controller(vec![Ok(tilting())]);
+    assert!(matches!(controller.tick(0), Tick::Sent(sample) if sample.roll.is_none()));
+
+    let config = Config { send_roll: true, ..RAW };
+    let mut controller = with_config(vec![Ok(tilting())], config);
+    let Tick::Sent(sample) = controller.tick(0) else { panic!("nothing sent") };
+    // starts where gravity says
+    let expected = (3.0f32).atan2(9.3);
+    assert!((sample.roll.unwrap() - expected).abs() < 1e-5);
+}
+
+#[test]
+fn roll_follows_the_gyro_between_readings() {
+    let config = Config { send_roll: true, ..RAW };
+    // turning at 1 rad/s for a second while gravity hasn't caught up
+    let mut controller = with_config(vec![Ok(spin(1.0)); 11], config);
+
+    let mut roll = 0.0;
+    for tick in 0..=10 {
+        let Tick::Sent(sample) = controller.tick(tick * 100) else { panic!("nothing sent") };
+        roll = sample.roll.unwrap();
+    }
+
+    // some of the turn survives the pull back to level
+    assert!(0.2 < roll && roll < 1.0, "{}", roll);
+}
+
+#[test]
+fn pulse_runs_the_motor_for_a_while() {
+    let mut feedback = Feedback::default();
+    let mut pins = Pins::default();
+
+    feedback.handle(Command::Pulse { millis: 150 }, 1000, &mut pins);
+    assert!(pins.motor);
+    feedback.update(1149, &mut pins);
+    assert!(pins.motor);
+    feedback.update(1150, &mut pins);
+    assert!(!pins.motor);
+}
+
+#[test]
+fn pulse_survives_the_clock_wrapping() {
+    let mut feedback = Feedback::default();
+    let mut pins = Pins::default();
+
+    feedback.handle(Command::Pulse { millis: 100 }, u32::MAX - 10, &mut pins);
+    feedback.update(u32::MAX, &mut pins);
+    assert!(pins.motor);
+    feedback.update(89, &mut pins);
+    assert!(!pins.motor);
+}
+
+#[test]
+fn flash_blinks_and_goes_back_to_the_status() {
+    let mut feedback = Feedback::default();
+    let mut pins = Pins::default();
+    feedback.handle(Command::Led { on: true }, 0, &mut pins);
+
+    feedback.handle(Command::Flash { times: 2 }, 100, &mut pins);
+    let mut lit = vec![pins.led];
+    // a status change in the middle waits for the flash to finish
+    feedback.handle(Command::Led { on: false }, 120, &mut pins);
+    for step in 1..=3 {
+        feedback.update(100 + step * FLASH_INTERVAL_MS, &mut pins);
+        lit.push(pins.led);
+    }
+
+    assert_eq!(lit, vec![true, false, true, false]);
+    // 1 for the status, 4 for the flash
+    assert_eq!(pins.led_changes, 5);
+}
+
+#[test]
+fn led_follows_the_status() {
+    let mut feedback = Feedback::default();
+    let mut pins = Pins::default();
+
+    feedback.handle(Command::Led { on: true }, 0, &mut pins);
+    assert!(pins.led);
+    feedback.handle(Command::Led { on: false }, 10, &mut pins);
+    assert!(!pins.led);
+}
@@ -0,0 +1,9 @@
+#![no_main]
+
+use controller_protocol::parse_frame;
+use libfuzzer_sys::fuzz_target;
+
+// whatever comes down the wire, the game mustn't fall over
+fuzz_target!(|line: &str| {
+    let _ = parse_frame(line);
+});
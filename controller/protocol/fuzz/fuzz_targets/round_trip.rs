@@ -0,0 +1,16 @@
+#![no_main]
+
+use controller_protocol::{parse_frame, Frame};
+use libfuzzer_sys::fuzz_target;
+
+// anything that parses has to be written back to something that parses the same
+fuzz_target!(|line: &str| {
+    let Ok(frame) = parse_frame(line) else {
+        return;
+    };
+    let mut written = String::new();
+    frame.write(&mut written).unwrap();
+    let reparsed = parse_frame(&written).expect("written frame doesn't parse");
+    // the legacy format comes back framed, but otherwise the same
+    assert_eq!(frame, reparsed);
+});
@@ -0,0 +1,310 @@
+//! Frames look like `@<kind> key=value key=value*CS` on their own line.
+//! CS is the xor of every byte between `@` and `*` as two hex digits.
+//!
+//! ```text
+//! @HELLO proto=1 dev=esp32-c3-rust rate=50*5F
+//! @S seq=42 t=4200 gx=0.01 gy=-0.2 gz=0 ax=0.12 ay=0.3 az=9.79*41
+//! ```
+//!
+//! `rate` is how many samples a second the controller sends. Controllers
+//! that estimate the roll themselves add it to samples as `roll`, in radians.
+//! A pedal or wheel sensor adds `rpm`, revolutions per minute. `btn` is
+//! the buttons held down as a bitmask, see `BUTTON_BELL`, and left out
+//! when none are.
+//!
+//! `seq` is left out of readings that never had one, like the old format
+//! written back out, so they aren't taken for a restart at 0.
+//!
+//! Unknown keys are skipped so newer firmware can add channels.
+//! A line with a single float is the old format, a gyro y reading.
+//! The game talks back with the same framing:
+//!
+//! ```text
+//! @PULSE ms=150*68
+//! @LED on=1*60
+//! @FLASH n=3*10
+//! ```
+#![no_std]
+
+use core::fmt::{self, Write};
+
+pub const PROTOCOL_VERSION: u32 = 1;
+pub const FRAME_START: char = '@';
+pub const CHECKSUM_START: char = '*';
+/// the bell on the handlebar, in `Sample::buttons`
+pub const BUTTON_BELL: u8 = 1 << 0;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Hello<'a> {
+    pub version: u32,
+    /// a single word, frames are split on whitespace
+    pub device: &'a str,
+    /// samples per second, if the controller sends at a fixed rate
+    pub rate_hz: Option<u32>,
+}
+
+/// Everything the controller reports in one reading. The legacy
+/// format only fills in `gyro[1]`.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Sample {
+    pub seq: Option<u32>,
+    pub timestamp_ms: Option<u32>,
+    /// angular velocity in rad/s
+    pub gyro: [f32; 3],
+    /// acceleration in m/s^2, if the controller sends it
+    pub accel: Option<[f32; 3]>,
+    /// bitmask of pressed buttons
+    pub buttons: u8,
+    /// roll in radians, positive to the left, if the controller works it out
+    pub roll: Option<f32>,
+    /// pedal or wheel revolutions per minute, if there's a sensor for it
+    pub cadence_rpm: Option<f32>,
+}
+
+/// Feedback the game sends to the controller.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Command {
+    /// run the vibration motor for a moment
+    Pulse { millis: u32 },
+    /// turn the status led on or off
+    Led { on: bool },
+    /// blink the status led a few times
+    Flash { times: u32 },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Frame<'a> {
+    Hello(Hello<'a>),
+    Sample(Sample),
+    Command(Command),
+}
+
+/// What was wrong with a line, pointing into it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error<'a> {
+    MissingChecksum,
+    BadChecksum { expected: u8, actual: u8 },
+    UnknownFrame(&'a str),
+    MissingField(&'static str),
+    BadField(&'a str),
+    NotANumber,
+}
+
+impl fmt::Display for Error<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::MissingChecksum => write!(f, "frame without checksum"),
+            Self::BadChecksum { expected, actual } => write!(f, "checksum {:02X} should be {:02X}", actual, expected),
+            Self::UnknownFrame(kind) => write!(f, "unknown frame {:?}", kind),
+            Self::MissingField(key) => write!(f, "missing field {:?}", key),
+            Self::BadField(field) => write!(f, "bad field {:?}", field),
+            Self::NotANumber => write!(f, "not a number"),
+        }
+    }
+}
+
+pub fn checksum(payload: &str) -> u8 {
+    payload.bytes().fold(0, |acc, byte| acc ^ byte)
+}
+
+/// Parses one trimmed, non-empty line in either the framed or the legacy format.
+pub fn parse_frame(line: &str) -> Result<Frame<'_>, Error<'_>> {
+    let Some(framed) = line.strip_prefix(FRAME_START) else {
+        return parse_legacy(line);
+    };
+
+    let (payload, sum) = framed
+        .rsplit_once(CHECKSUM_START)
+        .ok_or(Error::MissingChecksum)?;
+    // from_str_radix takes a sign, a checksum doesn't
+    if sum.is_empty() || sum.len() > 2 || !sum.bytes().all(|byte| byte.is_ascii_hexdigit()) {
+        return Err(Error::MissingChecksum);
+    }
+    let actual = u8::from_str_radix(sum, 16).map_err(|_| Error::MissingChecksum)?;
+    let expected = checksum(payload);
+    if actual != expected {
+        return Err(Error::BadChecksum { expected, actual });
+    }
+
+    let mut words = payload.split_whitespace();
+    let kind = words.next().unwrap_or_default();
+    if let Some(word) = words.clone().find(|word| !word.contains('=')) {
+        return Err(Error::BadField(word));
+    }
+    let fields = words.filter_map(Field::new);
+
+    match kind {
+        "HELLO" => parse_hello(fields).map(Frame::Hello),
+        "S" => parse_sample(fields).map(Frame::Sample),
+        "PULSE" | "LED" | "FLASH" => parse_command(kind, fields).map(Frame::Command),
+        _ => Err(Error::UnknownFrame(kind)),
+    }
+}
+
+impl Frame<'_> {
+    /// Writes the frame, without the newline. Floats are written in full
+    /// so parsing it again gives back the exact same sample.
+    pub fn write<W: Write>(&self, out: &mut W) -> fmt::Result {
+        out.write_char(FRAME_START)?;
+        let mut payload = Checksummed { out, checksum: 0 };
+        match self {
+            Self::Hello(hello) => {
+                write!(payload, "HELLO proto={} dev={}", hello.version, hello.device)?;
+                if let Some(rate) = hello.rate_hz {
+                    write!(payload, " rate={}", rate)?;
+                }
+            },
+            Self::Sample(sample) => {
+                write!(payload, "S")?;
+                if let Some(seq) = sample.seq {
+                    write!(payload, " seq={}", seq)?;
+                }
+                if let Some(t) = sample.timestamp_ms {
+                    write!(payload, " t={}", t)?;
+                }
+                let [gx, gy, gz] = sample.gyro;
+                write!(payload, " gx={} gy={} gz={}", gx, gy, gz)?;
+                if let Some([ax, ay, az]) = sample.accel {
+                    write!(payload, " ax={} ay={} az={}", ax, ay, az)?;
+                }
+                if sample.buttons != 0 {
+                    write!(payload, " btn={}", sample.buttons)?;
+                }
+                if let Some(roll) = sample.roll {
+                    write!(payload, " roll={}", roll)?;
+                }
+                if let Some(rpm) = sample.cadence_rpm {
+                    write!(payload, " rpm={}", rpm)?;
+                }
+            },
+            Self::Command(Command::Pulse { millis }) => write!(payload, "PULSE ms={}", millis)?,
+            Self::Command(Command::Led { on }) => write!(payload, "LED on={}", *on as u8)?,
+            Self::Command(Command::Flash { times }) => write!(payload, "FLASH n={}", times)?,
+        }
+        let checksum = payload.checksum;
+        write!(out, "{}{:02X}", CHECKSUM_START, checksum)
+    }
+}
+
+// a `key=value` word, kept whole for the error
+#[derive(Clone, Copy)]
+struct Field<'a> {
+    word: &'a str,
+    key: &'a str,
+    value: &'a str,
+}
+
+impl<'a> Field<'a> {
+    fn new(word: &'a str) -> Option<Self> {
+        let (key, value) = word.split_once('=')?;
+        Some(Self { word, key, value })
+    }
+
+    fn parse<T: core::str::FromStr>(&self) -> Result<T, Error<'a>> {
+        self.value.parse().map_err(|_| Error::BadField(self.word))
+    }
+
+    fn parse_float(&self) -> Result<f32, Error<'a>> {
+        match self.parse::<f32>()? {
+            value if value.is_finite() => Ok(value),
+            _ => Err(Error::BadField(self.word)),
+        }
+    }
+}
+
+// passes the payload through and xors it up on the way
+struct Checksummed<'w, W> {
+    out: &'w mut W,
+    checksum: u8,
+}
+
+impl<W: Write> Write for Checksummed<'_, W> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.checksum ^= checksum(s);
+        self.out.write_str(s)
+    }
+}
+
+fn parse_legacy(line: &str) -> Result<Frame<'_>, Error<'_>> {
+    match line.parse::<f32>() {
+        Ok(value) if value.is_finite() => Ok(Frame::Sample(Sample {
+            gyro: [0.0, value, 0.0],
+            ..Default::default()
+        })),
+        _ => Err(Error::NotANumber),
+    }
+}
+
+fn parse_hello<'a>(fields: impl Iterator<Item = Field<'a>>) -> Result<Hello<'a>, Error<'a>> {
+    let mut version = None;
+    let mut device = "";
+    let mut rate_hz = None;
+    for field in fields {
+        match field.key {
+            "proto" => version = Some(field.parse()?),
+            "dev" => device = field.value,
+            "rate" => rate_hz = Some(field.parse()?),
+            _ => {}
+        }
+    }
+
+    Ok(Hello {
+        version: version.ok_or(Error::MissingField("proto"))?,
+        device,
+        rate_hz,
+    })
+}
+
+fn parse_sample<'a>(fields: impl Iterator<Item = Field<'a>>) -> Result<Sample, Error<'a>> {
+    let mut sample = Sample::default();
+    let mut accel = [0.0; 3];
+    let mut has_accel = false;
+
+    for field in fields {
+        match field.key {
+            "seq" => sample.seq = Some(field.parse()?),
+            "t" => sample.timestamp_ms = Some(field.parse()?),
+            "gx" => sample.gyro[0] = field.parse_float()?,
+            "gy" => sample.gyro[1] = field.parse_float()?,
+            "gz" => sample.gyro[2] = field.parse_float()?,
+            "ax" | "ay" | "az" => {
+                let axis = match field.key {
+                    "ax" => 0,
+                    "ay" => 1,
+                    _ => 2,
+                };
+                accel[axis] = field.parse_float()?;
+                has_accel = true;
+            },
+            "btn" => sample.buttons = field.parse()?,
+            "roll" => sample.roll = Some(field.parse_float()?),
+            "rpm" => sample.cadence_rpm = Some(field.parse_float()?),
+            _ => {}
+        }
+    }
+
+    if has_accel {
+        sample.accel = Some(accel);
+    }
+    Ok(sample)
+}
+
+// each command has a single field
+fn parse_command<'a>(kind: &str, fields: impl Iterator<Item = Field<'a>>) -> Result<Command, Error<'a>> {
+    let key = match kind {
+        "PULSE" => "ms",
+        "LED" => "on",
+        _ => "n",
+    };
+    let mut value: Option<u32> = None;
+    for field in fields.filter(|field| field.key == key) {
+        value = Some(field.parse()?);
+    }
+    let value = value.ok_or(Error::MissingField(key))?;
+
+    Ok(match kind {
+        "PULSE" => Command::Pulse { millis: value },
+        "LED" => Command::Led { on: value != 0 },
+        _ => Command::Flash { times: value },
+    })
+}
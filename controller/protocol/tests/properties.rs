@@ -0,0 +1,138 @@
+use controller_protocol::*;
+use proptest::prelude::*;
+
+fn write(frame: &Frame) -> String {
+    let mut line = String::new();
+    frame.write(&mut line).unwrap();
+    line
+}
+
+fn finite() -> impl Strategy<Value = f32> {
+    prop::num::f32::NORMAL | prop::num::f32::SUBNORMAL | prop::num::f32::ZERO
+}
+
+prop_compose! {
+    fn sample()(
+        seq in any::<Option<u32>>(),
+        timestamp_ms in any::<Option<u32>>(),
+        gyro in [finite(), finite(), finite()],
+        accel in prop::option::of([finite(), finite(), finite()]),
+        buttons in any::<u8>(),
+        roll in prop::option::of(finite()),
+        cadence_rpm in prop::option::of(finite()),
+    ) -> Sample {
+        Sample { seq, timestamp_ms, gyro, accel, buttons, roll, cadence_rpm }
+    }
+}
+
+fn command() -> impl Strategy<Value = Command> {
+    prop_oneof![
+        any::<u32>().prop_map(|millis| Command::Pulse { millis }),
+        any::<bool>().prop_map(|on| Command::Led { on }),
+        any::<u32>().prop_map(|times| Command::Flash { times }),
+    ]
+}
+
+proptest! {
+    #[test]
+    fn samples_survive_a_round_trip(sample in sample()) {
+        let line = write(&Frame::Sample(sample));
+        prop_assert_eq!(parse_frame(&line), Ok(Frame::Sample(sample)));
+    }
+
+    #[test]
+    fn hellos_survive_a_round_trip(version in any::<u32>(), device in "[a-z0-9-]{0,24}", rate_hz in any::<Option<u32>>()) {
+        let hello = Hello { version, device: &device, rate_hz };
+        let line = write(&Frame::Hello(hello));
+        prop_assert_eq!(parse_frame(&line), Ok(Frame::Hello(hello)));
+    }
+
+    #[test]
+    fn commands_survive_a_round_trip(command in command()) {
+        let line = write(&Frame::Command(command));
+        prop_assert_eq!(parse_frame(&line), Ok(Frame::Command(command)));
+    }
+
+    #[test]
+    fn written_frames_are_a_single_line(sample in sample()) {
+        let line = write(&Frame::Sample(sample));
+        prop_assert!(line.starts_with(FRAME_START));
+        prop_assert!(!line.contains('\n') && !line.contains('\r'));
+    }
+
+    // xor catches any single changed byte
+    #[test]
+    fn corrupted_samples_are_rejected(sample in sample(), index in any::<prop::sample::Index>(), flip in 1u8..0x80) {
+        let line = write(&Frame::Sample(sample));
+        let payload_end = line.rfind(CHECKSUM_START).unwrap();
+        let at = 1 + index.index(payload_end - 1);
+        let mut bytes = line.into_bytes();
+        bytes[at] ^= flip;
+        // flipping below 0x80 keeps ascii ascii
+        let corrupted = String::from_utf8(bytes).unwrap();
+        prop_assert!(parse_frame(&corrupted).is_err());
+    }
+
+    #[test]
+    fn legacy_lines_are_a_gyro_y_reading(value in finite()) {
+        let expected = Sample { gyro: [0.0, value, 0.0], ..Default::default() };
+        let line = value.to_string();
+        prop_assert_eq!(parse_frame(&line), Ok(Frame::Sample(expected)));
+    }
+
+    // the host side fuzzing, see fuzz/ for the libfuzzer targets
+    #[test]
+    fn parsing_any_line_does_not_panic(line in any::<String>()) {
+        let _ = parse_frame(&line);
+    }
+
+    #[test]
+    fn parsing_almost_frames_does_not_panic(payload in "[A-Z]{1,5}( [a-z]{1,3}=[-+0-9.eEinfa]{0,8}){0,10}", sum in "[0-9A-Fa-f+-]{0,3}") {
+        let _ = parse_frame(&format!("@{}*{}", payload, sum));
+        let framed = format!("@{}*{:02X}", payload, checksum(&payload));
+        let _ = parse_frame(&framed);
+    }
+}
+
+#[test]
+fn documented_frames_parse() {
+    for line in [
+        "@HELLO proto=1 dev=arduino-esp32*52",
+        "@HELLO proto=1 dev=esp32-c3-rust rate=50*5F",
+        "@S seq=42 t=4200 gx=0.01 gy=-0.2 gz=0 ax=0.12 ay=0.3 az=9.79*41",
+        "@PULSE ms=150*68",
+        "@LED on=1*60",
+        "@FLASH n=3*10",
+    ] {
+        assert!(parse_frame(line).is_ok(), "{}", line);
+    }
+}
+
+#[test]
+fn documented_sample_is_what_gets_written() {
+    let sample = Sample {
+        seq: Some(42),
+        timestamp_ms: Some(4200),
+        gyro: [0.01, -0.2, 0.0],
+        accel: Some([0.12, 0.3, 9.79]),
+        ..Default::default()
+    };
+    assert_eq!(write(&Frame::Sample(sample)), "@S seq=42 t=4200 gx=0.01 gy=-0.2 gz=0 ax=0.12 ay=0.3 az=9.79*41");
+}
+
+#[test]
+fn legacy_readings_are_written_without_a_seq() {
+    let Ok(Frame::Sample(sample)) = parse_frame("-0.25") else {
+        panic!("legacy reading didn't parse");
+    };
+    let line = write(&Frame::Sample(sample));
+    assert!(!line.contains("seq="), "{}", line);
+    assert_eq!(parse_frame(&line), Ok(Frame::Sample(sample)));
+}
+
+#[test]
+fn errors_point_at_the_bad_field() {
+    let payload = "S seq=1 gy=fast";
+    let line = format!("@{}*{:02X}", payload, checksum(payload));
+    assert_eq!(parse_frame(&line), Err(Error::BadField("gy=fast")));
+}
@@ -0,0 +1,266 @@
+use std::{
+    process,
+    thread,
+    time::{Duration, Instant},
+};
+
+use controller_protocol::{parse_frame, Frame, Hello, Sample, BUTTON_BELL, PROTOCOL_VERSION};
+use rand::Rng;
+
+mod profile;
+mod pty;
+mod udp;
+
+use profile::{Step, TiltProfile, BELL_PRESS};
+use pty::Pty;
+use udp::Udp;
+
+const STANDARD_GRAVITY: f32 = 9.80665;
+
+const USAGE: &str = "\
+usage: controller-sim [options]
+
+Pretends to be the handlebar controller on a pseudo terminal and prints
+its path, start the game with `--serial-port <path>` to ride with it.
+With `--udp` it sends to the game over udp instead, like the esp32 on wi-fi.
+
+options:
+  --profile <sine|steps|keys|script>  how the handlebar moves (default sine)
+  --script <file>                     `seconds degrees` keyframes to loop, implies --profile script
+  --amplitude <degrees>               how far it tilts (default 20)
+  --period <seconds>                  how long one swing left and right takes (default 4)
+  --rate <hz>                         readings per second (default 10, like the arduino)
+  --noise <amount>                    random noise, in rad/s on the gyro and g on the accelerometer (default 0.01)
+  --cadence <rpm>                     pedal at this cadence, like a bike with a pedal sensor
+  --bell <seconds>                    ring the bell this often, with --profile keys b rings it
+  --udp <host:port>                   send to a game started with `--input udp`, e.g. 127.0.0.1:4210
+";
+
+struct Options {
+    profile: String,
+    script: Option<String>,
+    amplitude: f32,
+    period: f32,
+    rate: f32,
+    noise: f32,
+    cadence: Option<f32>,
+    bell: Option<f32>,
+    udp: Option<String>,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            profile: "sine".to_string(),
+            script: None,
+            amplitude: 20.0,
+            period: 4.0,
+            rate: 10.0,
+            noise: 0.01,
+            cadence: None,
+            bell: None,
+            udp: None,
+        }
+    }
+}
+
+impl Options {
+    // `--rate 50` or `--rate=50`
+    fn parse(args: impl Iterator<Item = String>) -> Result<Self, String> {
+        let mut options = Self::default();
+        let mut args = args.peekable();
+        while let Some(arg) = args.next() {
+            if arg == "--help" || arg == "-h" {
+                print!("{}", USAGE);
+                process::exit(0);
+            }
+            let (flag, value) = match arg.split_once('=') {
+                Some((flag, value)) => (flag.to_string(), value.to_string()),
+                None => {
+                    let value = args.next().ok_or_else(|| format!("{} needs a value", arg))?;
+                    (arg, value)
+                },
+            };
+            let number = || value.parse::<f32>()
+                .ok()
+                .filter(|number| number.is_finite() && *number >= 0.0)
+                .ok_or_else(|| format!("{} needs a number, got {:?}", flag, value));
+            match flag.as_str() {
+                "--profile" => options.profile = value.clone(),
+                "--script" => {
+                    options.profile = "script".to_string();
+                    options.script = Some(value.clone());
+                },
+                "--amplitude" => options.amplitude = number()?,
+                "--period" => options.period = number()?.max(0.1),
+                "--rate" => options.rate = number()?.max(0.1),
+                "--noise" => options.noise = number()?,
+                "--cadence" => options.cadence = Some(number()?),
+                "--bell" => options.bell = Some(number()?.max(BELL_PRESS * 2.0)),
+                "--udp" => options.udp = Some(value.clone()),
+                _ => return Err(format!("unknown option {}", flag)),
+            }
+        }
+        Ok(options)
+    }
+
+    fn tilt_profile(&self) -> Result<TiltProfile, String> {
+        let amplitude = self.amplitude.to_radians();
+        match self.profile.as_str() {
+            "sine" => Ok(TiltProfile::Sine { amplitude, period: self.period }),
+            "steps" => Ok(TiltProfile::Steps { amplitude, period: self.period }),
+            "keys" => TiltProfile::keys(amplitude).map_err(|e| format!("could not read the keyboard: {}", e)),
+            "script" => TiltProfile::script(self.script.as_deref().ok_or("--profile script needs --script <file>")?),
+            profile => Err(format!("unknown profile {:?}", profile)),
+        }
+    }
+}
+
+/// Where the lines go, and the game's feedback comes from.
+enum Link {
+    Pty(Pty),
+    Udp(Udp),
+}
+
+impl Link {
+    fn write_line(&mut self, line: &str) -> std::io::Result<()> {
+        match self {
+            Self::Pty(pty) => pty.write_line(line),
+            Self::Udp(udp) => udp.write_line(line),
+        }
+    }
+
+    fn read_available(&mut self) -> std::io::Result<Vec<u8>> {
+        match self {
+            Self::Pty(pty) => pty.read_available(),
+            Self::Udp(udp) => udp.read_available(),
+        }
+    }
+
+    fn name(&self) -> &str {
+        match self {
+            Self::Pty(pty) => &pty.path,
+            Self::Udp(udp) => &udp.target,
+        }
+    }
+}
+
+fn encode(frame: Frame) -> String {
+    let mut line = String::new();
+    // writing to a String can't fail
+    let _ = frame.write(&mut line);
+    line
+}
+
+// three decimals like the arduino prints, the lines get long otherwise
+fn rounded(value: f32) -> f32 {
+    (value * 1000.0).round() / 1000.0
+}
+
+fn main() {
+    let options = Options::parse(std::env::args().skip(1)).unwrap_or_else(|e| {
+        eprintln!("{}\n\n{}", e, USAGE);
+        process::exit(2);
+    });
+    let mut profile = options.tilt_profile().unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        process::exit(2);
+    });
+    let mut link = match &options.udp {
+        Some(target) => {
+            let udp = Udp::connect(target).unwrap_or_else(|e| {
+                eprintln!("could not send to {}: {}", target, e);
+                process::exit(1);
+            });
+            let port = target.rsplit(':').next().unwrap_or_default();
+            println!("simulated controller sending to {}", udp.target);
+            println!("start the game with: cargo run -p ridingintheparkwithparrots -- --input udp --udp-port {}", port);
+            Link::Udp(udp)
+        },
+        None => {
+            let pty = Pty::open().unwrap_or_else(|e| {
+                eprintln!("could not open a pseudo terminal: {}", e);
+                process::exit(1);
+            });
+            println!("simulated controller on {}", pty.path);
+            println!("start the game with: cargo run -p ridingintheparkwithparrots -- --serial-port {}", pty.path);
+            Link::Pty(pty)
+        },
+    };
+    if let TiltProfile::Keys { .. } = profile {
+        println!("a/d or the arrow keys lean, space straightens up, b rings the bell, q quits");
+    }
+
+    // what the arduino says on startup
+    let _ = link.write_line("MPU6050 Found!");
+    let _ = link.write_line(&encode(Frame::Hello(Hello {
+        version: PROTOCOL_VERSION,
+        device: "controller-sim",
+        rate_hz: Some(options.rate.round().max(1.0) as u32),
+    })));
+
+    let interval = Duration::from_secs_f32(1.0 / options.rate);
+    let started = Instant::now();
+    let mut rng = rand::thread_rng();
+    let mut noise = |scale: f32| if options.noise > 0.0 { rng.gen_range(-1.0..1.0) * options.noise * scale } else { 0.0 };
+    let mut previous: Option<(f32, f32)> = None;
+    let mut next_tick = started;
+    let mut seq: u32 = 0;
+    let mut commands = String::new();
+
+    loop {
+        let time = started.elapsed().as_secs_f32();
+        let delta = previous.map_or(0.0, |(at, _)| time - at);
+        let roll = match profile.step(time, delta) {
+            Step::Roll(roll) => roll,
+            Step::Quit => break,
+        };
+        // the handlebar turns about y, so gravity moves between z and x
+        let gy = previous
+            .filter(|_| delta > 0.0)
+            .map_or(0.0, |(_, last)| (roll - last) / delta);
+        previous = Some((time, roll));
+        let ringing = profile.ringing(time) || options.bell.is_some_and(|every| time % every > every - BELL_PRESS);
+
+        let sample = Sample {
+            seq: Some(seq),
+            timestamp_ms: Some(started.elapsed().as_millis() as u32),
+            gyro: [noise(1.0), gy + noise(1.0), noise(1.0)].map(rounded),
+            accel: Some([
+                -STANDARD_GRAVITY * roll.sin() + noise(STANDARD_GRAVITY),
+                noise(STANDARD_GRAVITY),
+                STANDARD_GRAVITY * roll.cos() + noise(STANDARD_GRAVITY),
+            ].map(rounded)),
+            buttons: if ringing { BUTTON_BELL } else { 0 },
+            roll: None,
+            cadence_rpm: options.cadence,
+        };
+        if let Err(e) = link.write_line(&encode(Frame::Sample(sample))) {
+            eprintln!("could not write to {}: {}", link.name(), e);
+            break;
+        }
+        seq = seq.wrapping_add(1);
+
+        // show the feedback the game sends back
+        match link.read_available() {
+            Ok(bytes) => commands.push_str(&String::from_utf8_lossy(&bytes)),
+            Err(e) => eprintln!("could not read from {}: {}", link.name(), e),
+        }
+        while let Some((line, rest)) = commands.split_once('\n') {
+            match parse_frame(line.trim()) {
+                Ok(Frame::Command(command)) => println!("game says {:?}", command),
+                _ => println!("game says something odd: {:?}", line.trim()),
+            }
+            commands = rest.to_string();
+        }
+
+        next_tick += interval;
+        let now = Instant::now();
+        if next_tick > now {
+            thread::sleep(next_tick - now);
+        } else {
+            // fell behind, don't try to catch up with a burst
+            next_tick = now;
+        }
+    }
+}
@@ -0,0 +1,162 @@
+use std::{
+    f32::consts::PI,
+    fs,
+    io::{self, Read},
+    os::fd::AsRawFd,
+};
+
+use crate::pty::{get_termios, set_termios};
+
+// how fast a key press swings the handlebar over, in rad/s
+pub const KEY_SLEW_RATE: f32 = PI / 2.0;
+// the terminal doesn't say when a key is let go, so a ring holds the bell this long
+pub const BELL_PRESS: f32 = 0.2;
+
+/// How the simulated rider moves the handlebar.
+pub enum TiltProfile {
+    /// swings left and right and back
+    Sine { amplitude: f32, period: f32 },
+    /// holds left, center, right and center again, a quarter period each
+    Steps { amplitude: f32, period: f32 },
+    /// `seconds degrees` keyframes from a file, looped
+    Script(Vec<(f32, f32)>),
+    /// a and d (or the arrow keys) lean, space straightens, b rings, q quits
+    Keys { amplitude: f32, target: f32, roll: f32, bell_until: f32, keyboard: Keyboard },
+}
+
+pub enum Step {
+    Roll(f32),
+    Quit,
+}
+
+impl TiltProfile {
+    pub fn keys(amplitude: f32) -> io::Result<Self> {
+        Ok(Self::Keys { amplitude, target: 0.0, roll: 0.0, bell_until: 0.0, keyboard: Keyboard::raw()? })
+    }
+
+    pub fn script(path: &str) -> Result<Self, String> {
+        let contents = fs::read_to_string(path).map_err(|e| format!("could not read {}: {}", path, e))?;
+        let mut keyframes = vec![];
+        for line in contents.lines().map(str::trim).filter(|line| !line.is_empty() && !line.starts_with('#')) {
+            let mut words = line.split_whitespace();
+            let (Some(seconds), Some(degrees), None) = (words.next(), words.next(), words.next()) else {
+                return Err(format!("expected `seconds degrees`, got {:?}", line));
+            };
+            let seconds: f32 = seconds.parse().map_err(|_| format!("bad time in {:?}", line))?;
+            let degrees: f32 = degrees.parse().map_err(|_| format!("bad angle in {:?}", line))?;
+            keyframes.push((seconds, degrees.to_radians()));
+        }
+        if keyframes.is_empty() || keyframes.windows(2).any(|pair| pair[1].0 <= pair[0].0) {
+            return Err(format!("{} needs keyframes in increasing time", path));
+        }
+        Ok(Self::Script(keyframes))
+    }
+
+    /// The roll in radians at `time` seconds, positive to the left.
+    pub fn step(&mut self, time: f32, delta: f32) -> Step {
+        match self {
+            Self::Sine { amplitude, period } => Step::Roll(*amplitude * (2.0 * PI * time / *period).sin()),
+            Self::Steps { amplitude, period } => {
+                let quarter = ((time / *period).fract() * 4.0) as u32;
+                Step::Roll(match quarter {
+                    0 => *amplitude,
+                    2 => -*amplitude,
+                    _ => 0.0,
+                })
+            },
+            Self::Script(keyframes) => Step::Roll(interpolate(keyframes, time)),
+            Self::Keys { amplitude, target, roll, bell_until, keyboard } => {
+                for key in keyboard.keys() {
+                    match key {
+                        Key::Left => *target = *amplitude,
+                        Key::Right => *target = -*amplitude,
+                        Key::Center => *target = 0.0,
+                        Key::Bell => *bell_until = time + BELL_PRESS,
+                        Key::Quit => return Step::Quit,
+                    }
+                }
+                let max_step = KEY_SLEW_RATE * delta;
+                *roll += (*target - *roll).clamp(-max_step, max_step);
+                Step::Roll(*roll)
+            },
+        }
+    }
+
+    /// Whether the rider is ringing the bell at `time`, only from the keyboard.
+    pub fn ringing(&self, time: f32) -> bool {
+        matches!(self, Self::Keys { bell_until, .. } if time < *bell_until)
+    }
+}
+
+fn interpolate(keyframes: &[(f32, f32)], time: f32) -> f32 {
+    let (last_time, last_roll) = keyframes[keyframes.len() - 1];
+    if keyframes.len() == 1 || last_time <= 0.0 {
+        return last_roll;
+    }
+    let time = time % last_time;
+    let next = keyframes.iter().position(|&(at, _)| at > time).unwrap_or(keyframes.len() - 1);
+    if next == 0 {
+        return keyframes[0].1;
+    }
+    let (from_time, from_roll) = keyframes[next - 1];
+    let (to_time, to_roll) = keyframes[next];
+    from_roll + (to_roll - from_roll) * (time - from_time) / (to_time - from_time)
+}
+
+enum Key {
+    Left,
+    Right,
+    Center,
+    Bell,
+    Quit,
+}
+
+/// Reads single key presses from the terminal, and puts it back the way it was when dropped.
+pub struct Keyboard {
+    original: libc::termios,
+}
+
+impl Keyboard {
+    fn raw() -> io::Result<Self> {
+        let fd = io::stdin().as_raw_fd();
+        let original = get_termios(fd)?;
+        let mut termios = original;
+        // no line buffering or echo, and reads return right away;
+        // signals are off too so ctrl-c gets here and the terminal is restored
+        termios.c_lflag &= !(libc::ICANON | libc::ECHO | libc::ISIG);
+        termios.c_cc[libc::VMIN] = 0;
+        termios.c_cc[libc::VTIME] = 0;
+        set_termios(fd, &termios)?;
+        Ok(Self { original })
+    }
+
+    fn keys(&mut self) -> Vec<Key> {
+        let mut buffer = [0u8; 32];
+        let n = io::stdin().lock().read(&mut buffer).unwrap_or(0);
+        let mut keys = vec![];
+        let mut bytes = buffer[..n].iter().peekable();
+        while let Some(byte) = bytes.next() {
+            match byte {
+                b'a' | b'A' => keys.push(Key::Left),
+                b'd' | b'D' => keys.push(Key::Right),
+                b' ' | b's' | b'S' => keys.push(Key::Center),
+                b'b' | b'B' => keys.push(Key::Bell),
+                b'q' | b'Q' | 3 => keys.push(Key::Quit),
+                // arrow keys are `ESC [ C` and `ESC [ D`
+                0x1b if bytes.next_if_eq(&&b'[').is_some() => match bytes.next() {
+                    Some(b'D') => keys.push(Key::Left),
+                    Some(b'C') => keys.push(Key::Right),
+                    _ => {},
+                },
+                _ => {},
+            }
+        }
+        keys
+    }
+}
+
+impl Drop for Keyboard {
+    fn drop(&mut self) {
+        let _ = set_termios(io::stdin().as_raw_fd(), &self.original);
+    }
+}
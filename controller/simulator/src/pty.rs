@@ -0,0 +1,101 @@
+use std::{
+    ffi::CStr,
+    fs::File,
+    io::{self, ErrorKind, Read, Write},
+    os::fd::{FromRawFd, RawFd},
+    ptr,
+};
+
+/// A pseudo terminal standing in for the controller's usb serial port.
+/// The game opens `path` like it would `/dev/ttyUSB0`.
+pub struct Pty {
+    pub path: String,
+    master: File,
+    // kept open so writes don't fail while the game isn't connected
+    _slave: File,
+}
+
+impl Pty {
+    pub fn open() -> io::Result<Self> {
+        let mut master: RawFd = -1;
+        let mut slave: RawFd = -1;
+        let opened = unsafe {
+            libc::openpty(&mut master, &mut slave, ptr::null_mut(), ptr::null_mut(), ptr::null_mut())
+        };
+        if opened != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let master_file = unsafe { File::from_raw_fd(master) };
+        let slave_file = unsafe { File::from_raw_fd(slave) };
+
+        let name = unsafe { libc::ttyname(slave) };
+        if name.is_null() {
+            return Err(io::Error::last_os_error());
+        }
+        let path = unsafe { CStr::from_ptr(name) }.to_string_lossy().into_owned();
+
+        // no echo or line editing, bytes go through like on a real serial port
+        set_raw(slave)?;
+        set_nonblocking(master)?;
+
+        Ok(Self {
+            path,
+            master: master_file,
+            _slave: slave_file,
+        })
+    }
+
+    /// Writes a line, or drops it if nobody has been reading and the buffer is full,
+    /// like a usb serial port does.
+    pub fn write_line(&mut self, line: &str) -> io::Result<()> {
+        match self.master.write_all(format!("{}\n", line).as_bytes()) {
+            Err(e) if e.kind() == ErrorKind::WouldBlock => Ok(()),
+            result => result,
+        }
+    }
+
+    /// Whatever the game sent since the last call.
+    pub fn read_available(&mut self) -> io::Result<Vec<u8>> {
+        let mut bytes = vec![];
+        let mut buffer = [0u8; 256];
+        loop {
+            match self.master.read(&mut buffer) {
+                Ok(0) => return Ok(bytes),
+                Ok(n) => bytes.extend_from_slice(&buffer[..n]),
+                Err(e) if e.kind() == ErrorKind::WouldBlock || e.kind() == ErrorKind::Interrupted => return Ok(bytes),
+                // linux reports EIO while the other end isn't open
+                Err(e) if e.raw_os_error() == Some(libc::EIO) => return Ok(bytes),
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
+
+pub fn set_raw(fd: RawFd) -> io::Result<()> {
+    let mut termios = get_termios(fd)?;
+    unsafe { libc::cfmakeraw(&mut termios) };
+    set_termios(fd, &termios)
+}
+
+pub fn set_nonblocking(fd: RawFd) -> io::Result<()> {
+    let flags = unsafe { libc::fcntl(fd, libc::F_GETFL) };
+    if flags < 0 || unsafe { libc::fcntl(fd, libc::F_SETFL, flags | libc::O_NONBLOCK) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+pub fn get_termios(fd: RawFd) -> io::Result<libc::termios> {
+    let mut termios = unsafe { std::mem::zeroed::<libc::termios>() };
+    if unsafe { libc::tcgetattr(fd, &mut termios) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(termios)
+}
+
+pub fn set_termios(fd: RawFd, termios: &libc::termios) -> io::Result<()> {
+    if unsafe { libc::tcsetattr(fd, libc::TCSANOW, termios) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
@@ -0,0 +1,51 @@
+use std::{
+    io::{self, ErrorKind},
+    net::UdpSocket,
+};
+
+/// Sends lines to the game over udp like the esp32 does on wi-fi,
+/// one datagram per line.
+pub struct Udp {
+    pub target: String,
+    socket: UdpSocket,
+}
+
+impl Udp {
+    pub fn connect(target: &str) -> io::Result<Self> {
+        let socket = UdpSocket::bind("0.0.0.0:0")?;
+        socket.connect(target)?;
+        socket.set_nonblocking(true)?;
+        Ok(Self {
+            target: target.to_string(),
+            socket,
+        })
+    }
+
+    /// Sends a line, or drops it if the game isn't listening yet.
+    pub fn write_line(&mut self, line: &str) -> io::Result<()> {
+        match self.socket.send(format!("{}\n", line).as_bytes()) {
+            Ok(_) => Ok(()),
+            Err(e) if is_unheard(&e) => Ok(()),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Whatever the game sent since the last call.
+    pub fn read_available(&mut self) -> io::Result<Vec<u8>> {
+        let mut bytes = vec![];
+        let mut buffer = [0u8; 1500];
+        loop {
+            match self.socket.recv(&mut buffer) {
+                Ok(n) => bytes.extend_from_slice(&buffer[..n]),
+                Err(e) if e.kind() == ErrorKind::WouldBlock || e.kind() == ErrorKind::Interrupted => return Ok(bytes),
+                Err(e) if is_unheard(&e) => return Ok(bytes),
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
+
+// a connected udp socket hears about a closed port on the next call
+fn is_unheard(e: &io::Error) -> bool {
+    e.kind() == ErrorKind::ConnectionRefused
+}
@@ -12,7 +12,7 @@ set -o xtrace
 readonly TARGET_HOST=lislis@raspberrypi.local
 readonly TARGET_PATH=/home/lislis/ridingintheparkwithparrots
 readonly TARGET_ARCH=armv7-unknown-linux-gnueabihf
-readonly SOURCE_PATH=./target/${TARGET_ARCH}/release/ridingintheparkwithparrots
+readonly SOURCE_PATH=../target/${TARGET_ARCH}/release/ridingintheparkwithparrots
 readonly PKG_CONFIG_LIBDIR_armv7_unknown_linux_gnueabihf=/usr/lib/arm-linux-gnueabihf/pkgconfig
 #PKG_CONFIG
 
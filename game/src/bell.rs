@@ -0,0 +1,102 @@
+use crate::*;
+
+// seconds before the bell does anything again, or it'd be rung all ride
+pub const BELL_COOLDOWN: f32 = 5.0;
+// seconds distressed parrots stop losing feathers after a ring
+pub const BELL_CALM: f32 = 2.5;
+
+/// The rider rang the bell, and it wasn't cooling down.
+#[derive(Event, Debug, Clone, Copy, PartialEq)]
+pub struct BellRungEvent;
+
+/// Whether the bell is held down, so holding it rings only once,
+/// and how long until it rings again and the parrots stay calm.
+#[derive(Resource, Debug)]
+pub struct Bell {
+    pub held: bool,
+    pub cooldown: Timer,
+    pub calm: Timer,
+}
+
+impl Default for Bell {
+    fn default() -> Self {
+        Self {
+            held: false,
+            cooldown: finished(BELL_COOLDOWN),
+            calm: finished(BELL_CALM),
+        }
+    }
+}
+
+impl Bell {
+    /// Whether the parrots are still listening to the last ring.
+    pub fn calming(&self) -> bool {
+        !self.calm.finished()
+    }
+}
+
+// a ride starts with the bell ready and nobody calmed yet
+fn finished(seconds: f32) -> Timer {
+    let mut timer = Timer::from_seconds(seconds, TimerMode::Once);
+    timer.tick(timer.duration());
+    timer
+}
+
+/// Rings the bell from the controller's button. Ringing it makes the
+/// parrots forget they're distressed for a moment.
+pub struct BellPlugin;
+
+impl Plugin for BellPlugin {
+    fn build(&self, app: &mut App) {
+        app
+        .init_resource::<Bell>()
+        .add_event::<BellRungEvent>()
+        .add_systems(OnEnter(GameState::Gameplay), reset_bell)
+        .add_systems(Update, ring_bell
+            .in_set(PlayerSet)
+            .run_if(in_state(GameState::Gameplay))
+            .run_if(controller_connected))
+        .add_systems(Update, play_bell
+            .after(ring_bell)
+            .run_if(in_state(GameState::Gameplay)))
+        ;
+    }
+}
+
+fn reset_bell(mut commands: Commands) {
+    commands.insert_resource(Bell::default());
+}
+
+fn ring_bell(
+    mut sample_events: EventReader<ControllerSampleEvent>,
+    mut bell: ResMut<Bell>,
+    time: Res<Time>,
+    mut rung_events: EventWriter<BellRungEvent>,
+) {
+    bell.cooldown.tick(time.delta());
+    bell.calm.tick(time.delta());
+
+    // only a press rings it, every reading in between counts
+    for ControllerSampleEvent(_, sample) in sample_events.iter().filter(|event| event.0 == PlayerId::FIRST) {
+        let pressed = sample.buttons & BUTTON_BELL != 0;
+        if pressed && !bell.held && bell.cooldown.finished() {
+            bell.cooldown.reset();
+            bell.calm.reset();
+            rung_events.send(BellRungEvent);
+        }
+        bell.held = pressed;
+    }
+}
+
+fn play_bell(
+    mut commands: Commands,
+    game_assets: Res<GameAssets>,
+    mut rung_events: EventReader<BellRungEvent>,
+) {
+    for _event in rung_events.iter() {
+        commands.spawn(AudioBundle {
+            source: game_assets.bell_sound.clone(),
+            settings: PlaybackSettings::DESPAWN,
+        });
+    }
+}
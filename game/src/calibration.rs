@@ -0,0 +1,343 @@
+use std::fs;
+
+use crate::*;
+
+pub const CALIBRATION_FILE: &str = "calibration.cfg";
+pub const CALIBRATION_STEP_SECONDS: f32 = 4.0;
+// deadzone is this much wider than the noise we saw while resting
+pub const DEADZONE_MARGIN: f32 = 1.5;
+pub const MIN_DEADZONE: f32 = 0.02;
+// sticks and phones drift a little at rest, as much as an uncalibrated handlebar
+pub const CENTERED_DEADZONE: f32 = 0.1;
+
+/// How raw controller readings are turned into `Movement.value`.
+/// Measured in the calibration screen and kept in `CALIBRATION_FILE`.
+#[derive(Resource, Debug, Clone, PartialEq, Reflect)]
+#[reflect(Resource)]
+pub struct CalibrationProfile {
+    /// reading while the handlebar is at rest
+    pub offset: f32,
+    /// readings closer than this to the offset are treated as neutral
+    pub deadzone: f32,
+    /// scales a full tilt to about 1.0, negative if the board is mounted mirrored
+    pub sensitivity: f32,
+}
+
+impl Default for CalibrationProfile {
+    // what the game used before there was calibration
+    fn default() -> Self {
+        Self {
+            offset: 0.0,
+            deadzone: 0.1,
+            sensitivity: 1.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CalibrationStep {
+    Rest,
+    Left,
+    Right,
+    Done,
+    Failed,
+}
+
+#[derive(Resource, Debug)]
+pub struct Calibration {
+    pub step: CalibrationStep,
+    pub timer: Timer,
+    rest: Vec<f32>,
+    left: Vec<f32>,
+    right: Vec<f32>,
+}
+
+#[derive(Component)]
+pub struct CalibrationText;
+
+#[derive(Component)]
+pub struct CalibrationBackButton;
+
+pub struct CalibrationPlugin;
+
+impl Plugin for CalibrationPlugin {
+    fn build(&self, app: &mut App) {
+        app
+        .register_type::<CalibrationProfile>()
+        .insert_resource(CalibrationProfile::load())
+        .add_systems(OnEnter(GameState::Calibration), spawn_menu_camera)
+        .add_systems(OnEnter(GameState::Calibration), start_calibration)
+        .add_systems(OnExit(GameState::Calibration), despawn_menu_camera)
+        .add_systems(Update, record_calibration.run_if(in_state(GameState::Calibration)))
+        .add_systems(Update, update_calibration_text.run_if(in_state(GameState::Calibration)))
+        .add_systems(Update, calibration_back_clicked.run_if(in_state(GameState::Calibration)))
+        ;
+    }
+}
+
+impl CalibrationProfile {
+    /// For inputs that are already centered and scaled, only ignoring the
+    /// drift around the middle. A full tilt still comes out as a full tilt.
+    pub fn centered() -> Self {
+        Self {
+            offset: 0.0,
+            deadzone: CENTERED_DEADZONE,
+            sensitivity: FULL_TILT / (FULL_TILT - CENTERED_DEADZONE),
+        }
+    }
+
+    pub fn load() -> Self {
+        let mut profile = Self::default();
+        let Ok(contents) = fs::read_to_string(CALIBRATION_FILE) else {
+            return profile;
+        };
+        for (key, value) in config_entries(&contents) {
+            let field = match key {
+                "offset" => &mut profile.offset,
+                "deadzone" => &mut profile.deadzone,
+                "sensitivity" => &mut profile.sensitivity,
+                _ => {
+                    warn!("ignoring unknown calibration setting {:?}", key);
+                    continue;
+                }
+            };
+            match value.parse::<f32>() {
+                Ok(value) if value.is_finite() => *field = value,
+                _ => warn!("ignoring invalid calibration value {}={:?}", key, value),
+            }
+        }
+        info!("loaded calibration {:?}", profile);
+        profile
+    }
+
+    pub fn save(&self) {
+        let contents = format!(
+            "# written by the calibration screen\noffset = {}\ndeadzone = {}\nsensitivity = {}\n",
+            self.offset, self.deadzone, self.sensitivity
+        );
+        if let Err(e) = fs::write(CALIBRATION_FILE, contents) {
+            error!("could not save calibration to {}: {}", CALIBRATION_FILE, e);
+        }
+    }
+
+    pub fn apply(&self, raw: f32) -> f32 {
+        let centered = raw - self.offset;
+        if centered.abs() <= self.deadzone {
+            return 0.0;
+        }
+        (centered - self.deadzone.copysign(centered)) * self.sensitivity
+    }
+
+    // the rider rests, then tilts fully left and then fully right
+    fn measure(rest: &[f32], left: &[f32], right: &[f32]) -> Option<Self> {
+        if rest.is_empty() || left.is_empty() || right.is_empty() {
+            return None;
+        }
+
+        let offset = rest.iter().sum::<f32>() / rest.len() as f32;
+        let noise = rest.iter()
+            .map(|value| (value - offset).abs())
+            .fold(0.0, f32::max);
+        let deadzone = (noise * DEADZONE_MARGIN).max(MIN_DEADZONE);
+
+        // the reading furthest from rest, keeping its sign
+        let peak = |values: &[f32]| values.iter()
+            .map(|value| value - offset)
+            .fold(0.0f32, |peak, value| if value.abs() > peak.abs() { value } else { peak });
+        let left_peak = peak(left);
+        let right_peak = peak(right);
+
+        // both sides have to clear the deadzone, in opposite directions
+        let range = left_peak.abs().min(right_peak.abs());
+        if range <= deadzone || left_peak.signum() == right_peak.signum() {
+            return None;
+        }
+
+        Some(Self {
+            offset,
+            deadzone,
+            sensitivity: left_peak.signum() * FULL_TILT / (range - deadzone),
+        })
+    }
+}
+
+impl CalibrationStep {
+    fn instructions(&self) -> &'static str {
+        match self {
+            Self::Rest => "Hold the handlebar still and straight",
+            Self::Left => "Now tilt it all the way to the left, and back",
+            Self::Right => "Now tilt it all the way to the right, and back",
+            Self::Done => "All done, the calibration is saved",
+            Self::Failed => "That didn't work, the handlebar barely moved. Give it another go from the main menu",
+        }
+    }
+}
+
+fn start_calibration(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+) {
+    commands.insert_resource(Calibration {
+        step: CalibrationStep::Rest,
+        timer: Timer::from_seconds(CALIBRATION_STEP_SECONDS, TimerMode::Repeating),
+        rest: vec![],
+        left: vec![],
+        right: vec![],
+    });
+
+    let back_button = spawn_button(&mut commands, &asset_server, "Main menu", Color::LIME_GREEN);
+    commands.entity(back_button).insert(CalibrationBackButton);
+
+    commands.spawn((
+        NodeBundle {
+            style: Style {
+                width: Val::Percent(100.0),
+                height: Val::Percent(100.0),
+                justify_content: JustifyContent::Center,
+                flex_direction: FlexDirection::Column,
+                ..default()
+            },
+            background_color: Color::DARK_GRAY.into(),
+            ..default()
+        },
+        MenuUIRoot,
+        Name::new("Calibration"),
+    )).with_children(|commands| {
+        commands.spawn((
+            TextBundle {
+                style: Style {
+                    align_self: AlignSelf::Center,
+                    margin: UiRect::all(Val::Percent(3.0)),
+                    max_width: Val::Percent(70.0),
+                    ..default()
+                },
+                text: Text::from_section("", TextStyle {
+                    font: asset_server.load("fonts/Gorditas-Bold.ttf"),
+                    font_size: 64.0,
+                    color: Color::BLACK,
+                }),
+                ..default()
+            },
+            CalibrationText,
+        ));
+    })
+    .add_child(back_button);
+}
+
+fn record_calibration(
+    mut calibration: ResMut<Calibration>,
+    mut profile: ResMut<CalibrationProfile>,
+    movement_q: Query<(&Movement, &PlayerId)>,
+    time: Res<Time>,
+) {
+    let Some(movement) = movement_of(&movement_q, PlayerId::FIRST) else {
+        return;
+    };
+
+    match calibration.step {
+        CalibrationStep::Rest => calibration.rest.push(movement.raw),
+        CalibrationStep::Left => calibration.left.push(movement.raw),
+        CalibrationStep::Right => calibration.right.push(movement.raw),
+        CalibrationStep::Done | CalibrationStep::Failed => return,
+    }
+
+    calibration.timer.tick(time.delta());
+    if !calibration.timer.just_finished() {
+        return;
+    }
+
+    calibration.step = match calibration.step {
+        CalibrationStep::Rest => CalibrationStep::Left,
+        CalibrationStep::Left => CalibrationStep::Right,
+        _ => match CalibrationProfile::measure(&calibration.rest, &calibration.left, &calibration.right) {
+            Some(measured) => {
+                info!("calibrated {:?}", measured);
+                measured.save();
+                *profile = measured;
+                CalibrationStep::Done
+            },
+            None => CalibrationStep::Failed,
+        },
+    };
+}
+
+fn update_calibration_text(
+    calibration: Res<Calibration>,
+    mut text_q: Query<&mut Text, With<CalibrationText>>,
+) {
+    let mut text = text_q.single_mut();
+    text.sections[0].value = match calibration.step {
+        CalibrationStep::Done | CalibrationStep::Failed => calibration.step.instructions().to_string(),
+        _ => format!("{} ({:.0})", calibration.step.instructions(), calibration.timer.remaining_secs().ceil()),
+    };
+}
+
+fn calibration_back_clicked(
+    mut commands: Commands,
+    interactions: Query<&Interaction, (With<CalibrationBackButton>, Changed<Interaction>)>,
+    menu_root: Query<Entity, With<MenuUIRoot>>,
+    mut game_state: ResMut<NextState<GameState>>,
+    mut mouse_input: ResMut<Input<MouseButton>>,
+) {
+    for interaction in &interactions {
+        if matches!(interaction, Interaction::Pressed) {
+            let root_entity = menu_root.single();
+            commands.entity(root_entity).despawn_recursive();
+            commands.remove_resource::<Calibration>();
+            game_state.set(GameState::MainMenu);
+            mouse_input.clear();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(a: f32, b: f32) -> bool {
+        (a - b).abs() < 1e-5
+    }
+
+    #[test]
+    fn measured_tilts_come_out_full() {
+        let rest = [0.49, 0.5, 0.51];
+        let profile = CalibrationProfile::measure(&rest, &[0.5, 1.5, 0.6], &[0.5, -0.5, 0.4]).unwrap();
+        assert!(close(profile.offset, 0.5));
+        assert_eq!(profile.deadzone, MIN_DEADZONE);
+        assert!(close(profile.apply(1.5), FULL_TILT));
+        assert!(close(profile.apply(-0.5), -FULL_TILT));
+        for value in rest {
+            assert_eq!(profile.apply(value), 0.0);
+        }
+    }
+
+    #[test]
+    fn a_mirrored_board_still_leans_left_to_the_left() {
+        let profile = CalibrationProfile::measure(&[0.0], &[-2.0], &[2.0]).unwrap();
+        assert!(profile.sensitivity < 0.0);
+        assert!(close(profile.apply(-2.0), FULL_TILT));
+        assert!(close(profile.apply(2.0), -FULL_TILT));
+    }
+
+    #[test]
+    fn noise_at_rest_widens_the_deadzone() {
+        let profile = CalibrationProfile::measure(&[-0.2, 0.2], &[2.0], &[-2.0]).unwrap();
+        assert!(close(profile.deadzone, 0.2 * DEADZONE_MARGIN));
+        assert_eq!(profile.apply(0.25), 0.0);
+    }
+
+    #[test]
+    fn barely_moving_is_not_a_calibration() {
+        assert_eq!(CalibrationProfile::measure(&[], &[1.0], &[-1.0]), None);
+        assert_eq!(CalibrationProfile::measure(&[0.0], &[0.01], &[-1.0]), None);
+        assert_eq!(CalibrationProfile::measure(&[0.0], &[1.0], &[0.5]), None);
+    }
+
+    #[test]
+    fn centered_inputs_only_lose_the_drift() {
+        let profile = CalibrationProfile::centered();
+        assert_eq!(profile.apply(CENTERED_DEADZONE * 0.5), 0.0);
+        assert!(close(profile.apply(FULL_TILT), FULL_TILT));
+        assert!(close(profile.apply(-FULL_TILT), -FULL_TILT));
+    }
+}
@@ -0,0 +1,92 @@
+use std::fs;
+
+use crate::*;
+
+pub const GAME_CONFIG_FILE: &str = "game.cfg";
+
+/// How the game is played, rather than what with, that's `ControllerConfig`.
+/// Values are taken from `GAME_CONFIG_FILE`, then the command line, later ones winning.
+#[derive(Resource, Debug, Clone, PartialEq)]
+pub struct GameConfig {
+    pub line: LineMode,
+    /// directory to record rides into
+    pub record: Option<String>,
+    /// recorded ride to play back instead of reading input
+    pub replay: Option<String>,
+    /// the park to ride, inside `assets`
+    pub level: String,
+}
+
+impl Default for GameConfig {
+    fn default() -> Self {
+        Self {
+            line: LineMode::default(),
+            record: None,
+            replay: None,
+            level: DEFAULT_LEVEL.to_string(),
+        }
+    }
+}
+
+/// Reads the `GameConfig`. The plugins using it go after this one,
+/// without it they play with the defaults.
+pub struct ConfigPlugin;
+
+impl Plugin for ConfigPlugin {
+    fn build(&self, app: &mut App) {
+        let config = GameConfig::load();
+        app
+        .register_type::<LineMode>()
+        .insert_resource(config.line)
+        .insert_resource(config)
+        ;
+    }
+}
+
+impl GameConfig {
+    pub fn load() -> Self {
+        let mut config = Self::default();
+        if let Ok(contents) = fs::read_to_string(GAME_CONFIG_FILE) {
+            for (key, value) in config_entries(&contents) {
+                config.set(key, value);
+            }
+        }
+        config.apply_args(std::env::args().skip(1));
+        config
+    }
+
+    // `--line lean` or `--line=lean`, same for `--record`, `--replay` and `--level`,
+    // the controller's flags are left to `ControllerConfig`
+    fn apply_args(&mut self, args: impl Iterator<Item = String>) {
+        let mut args = args.peekable();
+        while let Some(arg) = args.next() {
+            let (flag, inline_value) = match arg.split_once('=') {
+                Some((flag, value)) => (flag.to_string(), Some(value.to_string())),
+                None => (arg, None),
+            };
+            let key = match flag.as_str() {
+                "--line" => "line",
+                "--record" => "record",
+                "--replay" => "replay",
+                "--level" => "level",
+                _ => continue,
+            };
+            if let Some(value) = inline_value.or_else(|| args.next()) {
+                self.set(key, &value);
+            }
+        }
+    }
+
+    pub fn set(&mut self, key: &str, value: &str) {
+        match key {
+            "record" if !value.is_empty() => self.record = Some(value.to_string()),
+            "replay" if !value.is_empty() => self.replay = Some(value.to_string()),
+            "level" if !value.is_empty() => self.level = value.to_string(),
+            "line" => match value.parse() {
+                Ok(line) => self.line = line,
+                Err(e) => warn!("ignoring {}", e),
+            },
+            _ => warn!("ignoring unknown game setting {:?}", key),
+        }
+    }
+}
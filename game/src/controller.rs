@@ -1,88 +1,704 @@
+use std::{fs, io::Read, net::UdpSocket, time::{Duration, Instant}};
+
 use crate::*;
 
-//pub const SERIAL_PORT: &str = "/dev/ttyUSB0";
-//pub const SERIAL_PORT: &str = "/dev/ttyACM0";
-pub const SERIAL_PORT: &str = "/dev/cu.usbserial-0001";
+pub const DEFAULT_BAUD_RATE: u32 = 115200;
+pub const CONFIG_FILE: &str = "controller.cfg";
+pub const PORT_ENV: &str = "PARROTS_SERIAL_PORT";
+pub const BAUD_ENV: &str = "PARROTS_BAUD_RATE";
+pub const INPUT_ENV: &str = "PARROTS_INPUT";
+pub const STEERING_ENV: &str = "PARROTS_STEERING";
+pub const UDP_PORT_ENV: &str = "PARROTS_UDP_PORT";
+pub const PHONE_PORT_ENV: &str = "PARROTS_PHONE_PORT";
+pub const PROBE_TIMEOUT: Duration = Duration::from_millis(1500);
+pub const CONTROLLER_LABEL: &str = "controller";
+
+// usb to serial chips found on the esp32 boards we use
+// (cp210x on the devkit v1, ch340 clones, the c3's builtin usb jtag/serial, ftdi)
+const ESP32_USB_IDS: [(u16, u16); 5] = [
+    (0x10c4, 0xea60),
+    (0x1a86, 0x7523),
+    (0x1a86, 0x55d4),
+    (0x303a, 0x1001),
+    (0x0403, 0x6001),
+];
+
+/// Where to find the controller. Values are taken from the config file,
+/// then the environment, then the command line, later ones winning.
+/// Without a port the available serial ports are searched for an esp32.
+#[derive(Resource, Debug, Clone, PartialEq)]
+pub struct ControllerConfig {
+    pub backend: InputBackend,
+    pub port: Option<String>,
+    pub baud_rate: u32,
+    /// more handlebars on serial, one for each player after the first
+    pub controllers: Vec<ControllerPort>,
+    /// where to listen for a controller on wi-fi, with `input = udp`
+    pub udp_port: u16,
+    /// where to serve the phone page, with `input = phone`
+    pub phone_port: u16,
+    pub steering: Steering,
+    pub pedalling: Pedalling,
+}
+
+impl Default for ControllerConfig {
+    fn default() -> Self {
+        Self {
+            backend: InputBackend::Serial,
+            port: None,
+            baud_rate: DEFAULT_BAUD_RATE,
+            controllers: vec![],
+            udp_port: DEFAULT_UDP_PORT,
+            phone_port: DEFAULT_PHONE_PORT,
+            steering: Steering::default(),
+            pedalling: Pedalling::default(),
+        }
+    }
+}
+
+/// Another handlebar on a serial port of its own, `controller = <label>:<port>`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ControllerPort {
+    pub label: String,
+    pub port: String,
+}
+
+/// Who's riding. Every `Movement` has one, and so does the player on the bike.
+#[derive(Component, Debug, Default, Clone, Copy, PartialEq, Eq, Hash, Reflect)]
+#[reflect(Component)]
+pub struct PlayerId(pub usize);
+
+impl PlayerId {
+    /// Rides with the input backend, and with the `controller` label on serial or udp.
+    pub const FIRST: Self = Self(0);
+}
+
+/// A player and the label of the controller they ride with.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Rider {
+    pub player: PlayerId,
+    pub label: String,
+}
 
-#[derive(PartialEq, Debug, Reflect)]
+/// Everyone with a controller, the first player first.
+#[derive(Resource, Debug, Clone, PartialEq)]
+pub struct Riders(pub Vec<Rider>);
+
+impl Riders {
+    pub fn player(&self, label: &str) -> Option<PlayerId> {
+        self.0.iter().find(|rider| rider.label == label).map(|rider| rider.player)
+    }
+}
+
+#[derive(PartialEq, Debug, Default, Reflect)]
 pub enum Dir {
     Left,
     Right,
+    #[default]
     Neutral
 }
 
-impl Default for Dir {
-    fn default() -> Self {Self::Neutral}
-}
-
 #[derive(Debug, PartialEq, Component, Default, Reflect)]
 #[reflect(Component)]
 pub struct Movement {
+    /// the reading as it came from the controller, or the centered roll
+    /// if it sends the accelerometer or its own roll too
+    pub raw: f32,
+    /// the reading after calibration, about 1.0 when fully tilted left
     pub value: f32,
     pub direction: Dir,
+    pub roll: RollFilter,
+    /// the latest cadence, if the controller has a pedal sensor
+    pub cadence_rpm: Option<f32>,
     //moving_back: bool
 }
 
+/// Whether input is coming in. Only the handlebar controller can go away,
+/// over serial or udp, the other backends are always connected. The
+/// handlebar starts out waiting until it's found or first heard from, a serial
+/// one that doesn't turn up at startup hands over to the keyboard.
+#[derive(Resource, Debug)]
+pub struct ControllerStatus {
+    pub connected: bool,
+}
+
+#[derive(Component)]
+pub struct DisconnectedOverlay;
 
-pub struct ControllerPlugin;
+/// A reading for one player, from whichever input backend is active.
+/// Everything that ends up in `Movement` goes through here, so it can be recorded.
+#[derive(Event, Debug, Clone, PartialEq)]
+pub struct ControllerSampleEvent(pub PlayerId, pub ControllerSample);
+
+/// The input backends run in here, everything reading `Movement` after.
+#[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct InputSet;
+
+
+pub struct ControllerPlugin {
+    pub config: ControllerConfig,
+}
+
+impl ControllerPlugin {
+    pub fn new(config: ControllerConfig) -> Self {
+        Self { config }
+    }
+}
 
 impl Plugin for ControllerPlugin {
     fn build(&self, app: &mut App) {
+        // a replay stands in for the controller
+        let game = app.world.get_resource::<GameConfig>().cloned().unwrap_or_default();
+        let mut config = self.config.clone();
+        if game.replay.is_some() {
+            config.backend = InputBackend::Replay;
+        }
+
+        let (backend, serial, udp) = config.open_backend();
+        info!("reading input from {:?}", backend);
+        let riders = config.riders();
+        for rider in riders.0.iter().skip(1) {
+            info!("player {} rides with {}", rider.player.0 + 1, rider.label);
+        }
+
         app
         .register_type::<Movement>()
-        .add_plugins(SerialPlugin::new(SERIAL_PORT, 115200))
+        .register_type::<PlayerId>()
+        .insert_resource(riders)
+        .insert_resource(config.clone())
+        .insert_resource(backend)
+        .insert_resource(config.steering)
+        .insert_resource(config.pedalling)
+        .register_type::<Pedalling>()
+        .init_resource::<SerialLines>()
+        .insert_resource(ControllerStatus { connected: !matches!(backend, InputBackend::Serial | InputBackend::Udp) })
+        .add_event::<ControllerSampleEvent>()
+        .add_plugins(serial)
+        .add_plugins(udp)
+        .add_plugins(ReplayPlugin::new(&game))
+        .add_plugins(PhonePlugin::new(&config))
+        .add_plugins(FeedbackPlugin)
         .add_systems(OnEnter(GameState::Gameplay), init_movement)
         .add_systems(OnExit(GameState::Gameplay), rm_movement)
-        .add_systems(Update, read_serial.run_if(in_state(GameState::Gameplay)))
+        .add_systems(OnEnter(GameState::Calibration), init_movement)
+        .add_systems(OnExit(GameState::Calibration), rm_movement)
+        // more handlebars can be on serial whatever the first player uses
+        .add_systems(Update, read_serial
+            .in_set(InputSet)
+            .run_if(reading_input()))
+        .add_systems(Update, read_udp
+            .in_set(InputSet)
+            .run_if(reading_input())
+            .run_if(resource_equals(InputBackend::Udp)))
+        .add_systems(Update, read_keyboard
+            .in_set(InputSet)
+            .run_if(reading_input())
+            .run_if(resource_equals(InputBackend::Keyboard)))
+        .add_systems(Update, read_gamepad
+            .in_set(InputSet)
+            .run_if(reading_input())
+            .run_if(resource_equals(InputBackend::Gamepad)))
+        .add_systems(Update, apply_samples
+            .in_set(InputSet)
+            .after(read_serial)
+            .after(read_udp)
+            .after(read_keyboard)
+            .after(read_gamepad)
+            .run_if(reading_input()))
+        .add_systems(Update, track_connection)
+        .add_systems(Update, update_disconnected_overlay.run_if(in_state(GameState::Gameplay)))
+        .add_systems(OnExit(GameState::Gameplay), rm_disconnected_overlay)
         ;
     }
 }
 
+// `Movement` only exists while riding or calibrating
+pub fn reading_input() -> impl Condition<()> {
+    in_state(GameState::Gameplay).or_else(in_state(GameState::Calibration))
+}
+
+impl ControllerConfig {
+    pub fn load() -> Self {
+        let mut config = Self::default();
+        if let Ok(contents) = fs::read_to_string(CONFIG_FILE) {
+            config.apply_file(&contents);
+        }
+        config.apply_env();
+        config.apply_args(std::env::args().skip(1));
+        config
+    }
+
+    fn apply_file(&mut self, contents: &str) {
+        for (key, value) in config_entries(contents) {
+            self.set(key, value);
+        }
+    }
+
+    fn apply_env(&mut self) {
+        if let Ok(port) = std::env::var(PORT_ENV) {
+            self.set("port", &port);
+        }
+        if let Ok(baud_rate) = std::env::var(BAUD_ENV) {
+            self.set("baud_rate", &baud_rate);
+        }
+        if let Ok(input) = std::env::var(INPUT_ENV) {
+            self.set("input", &input);
+        }
+        if let Ok(steering) = std::env::var(STEERING_ENV) {
+            self.set("steering", &steering);
+        }
+        if let Ok(udp_port) = std::env::var(UDP_PORT_ENV) {
+            self.set("udp_port", &udp_port);
+        }
+        if let Ok(phone_port) = std::env::var(PHONE_PORT_ENV) {
+            self.set("phone_port", &phone_port);
+        }
+    }
+
+    // `--serial-port /dev/ttyUSB0` or `--serial-port=/dev/ttyUSB0`,
+    // same for `--baud-rate`, `--controller`, `--udp-port`, `--phone-port`, `--input`, `--steering` and `--response`
+    fn apply_args(&mut self, args: impl Iterator<Item = String>) {
+        let mut args = args.peekable();
+        while let Some(arg) = args.next() {
+            let (flag, inline_value) = match arg.split_once('=') {
+                Some((flag, value)) => (flag.to_string(), Some(value.to_string())),
+                None => (arg, None),
+            };
+            let key = match flag.as_str() {
+                "--serial-port" => "port",
+                "--baud-rate" => "baud_rate",
+                "--controller" => "controller",
+                "--udp-port" => "udp_port",
+                "--phone-port" => "phone_port",
+                "--input" => "input",
+                "--steering" => "steering",
+                "--response" => "response",
+                _ => continue,
+            };
+            if let Some(value) = inline_value.or_else(|| args.next()) {
+                self.set(key, &value);
+            }
+        }
+    }
+
+    pub fn set(&mut self, key: &str, value: &str) {
+        match key {
+            "port" if !value.is_empty() => self.port = Some(value.to_string()),
+            "controller" => match value.split_once(':').map(|(label, port)| (label.trim(), port.trim())) {
+                Some((label, port)) if !label.is_empty() && !port.is_empty() => {
+                    if label == CONTROLLER_LABEL || self.controllers.iter().any(|controller| controller.label == label) {
+                        warn!("ignoring controller {:?}, the label is taken", value);
+                    } else {
+                        self.controllers.push(ControllerPort { label: label.to_string(), port: port.to_string() });
+                    }
+                },
+                _ => warn!("ignoring controller {:?}, expected <label>:<port>", value),
+            },
+            "baud_rate" => match value.parse() {
+                Ok(baud_rate) => self.baud_rate = baud_rate,
+                Err(_) => warn!("ignoring invalid baud rate {:?}", value),
+            },
+            "udp_port" | "phone_port" => match value.parse() {
+                Ok(port) if key == "udp_port" => self.udp_port = port,
+                Ok(port) => self.phone_port = port,
+                Err(_) => warn!("ignoring invalid {} {:?}", key, value),
+            },
+            "input" => match value.parse() {
+                Ok(backend) => self.backend = backend,
+                Err(e) => warn!("ignoring {}", e),
+            },
+            "steering" => match value.parse() {
+                Ok(mode) => self.steering.mode = mode,
+                Err(e) => warn!("ignoring {}", e),
+            },
+            "response" => match value.parse() {
+                Ok(curve) => self.steering.curve = curve,
+                Err(e) => warn!("ignoring {}", e),
+            },
+            "full_speed_rpm" => match value.parse::<f32>() {
+                Ok(rpm) if rpm.is_finite() && rpm > 0.0 => self.pedalling.full_speed_rpm = rpm,
+                _ => warn!("ignoring invalid {} {:?}", key, value),
+            },
+            "steering_rate" | "response_exponent" | "response_deadzone" => match value.parse::<f32>() {
+                Ok(number) if number.is_finite() => match key {
+                    "steering_rate" => self.steering.rate = number,
+                    "response_exponent" => self.steering.exponent = number,
+                    _ => self.steering.deadzone = number.clamp(0.0, 0.99),
+                },
+                _ => warn!("ignoring invalid {} {:?}", key, value),
+            },
+            _ => warn!("ignoring unknown controller setting {:?}", key),
+        }
+    }
+
+    /// The first player and one for each of `controllers`. A replay
+    /// only has the first, it's the only one recorded.
+    pub fn riders(&self) -> Riders {
+        let first = Rider { player: PlayerId::FIRST, label: CONTROLLER_LABEL.to_string() };
+        let others = self.controllers.iter()
+            .filter(|_| self.backend != InputBackend::Replay)
+            .enumerate()
+            .map(|(i, controller)| Rider { player: PlayerId(i + 1), label: controller.label.clone() });
+        Riders(std::iter::once(first).chain(others).collect())
+    }
+
+    /// Picks the backend to read from. Falls back to the keyboard if the
+    /// udp port is taken, or in `track_connection` if the serial controller
+    /// can't be found or opened at startup, after that a lost one is waited for.
+    /// The other players' controllers are opened whichever it is.
+    pub fn open_backend(&self) -> (InputBackend, SerialPlugin, UdpPlugin) {
+        // they're waited for like the first one, even if they aren't there yet
+        let others: Vec<_> = self.controllers.iter()
+            .filter(|_| self.backend != InputBackend::Replay)
+            .map(|controller| SerialSetting {
+                label: controller.label.clone(),
+                port_name: Some(controller.port.clone()),
+                baud_rate: self.baud_rate,
+                auto_detect: false,
+                taken: vec![],
+                fallback: false,
+            })
+            .collect();
+        let no_serial = SerialPlugin { settings: others.clone() };
+        let no_udp = UdpPlugin { socket: None };
+        if self.backend == InputBackend::Udp {
+            return match UdpSocket::bind(("0.0.0.0", self.udp_port)) {
+                Ok(socket) => {
+                    info!("listening for a controller on udp port {}", self.udp_port);
+                    (InputBackend::Udp, no_serial, UdpPlugin { socket: Some(socket) })
+                },
+                Err(e) => {
+                    warn!("could not listen on udp port {}: {}, falling back to keyboard", self.udp_port, e);
+                    (InputBackend::Keyboard, no_serial, no_udp)
+                },
+            };
+        }
+        if self.backend != InputBackend::Serial {
+            // a replay falls back in `ReplayPlugin` if the recording won't load,
+            // the phone in `PhonePlugin` if its port is taken
+            return (self.backend, no_serial, no_udp);
+        }
+
+        // looking for it and opening it happen on the serial thread, until
+        // then the ride waits for it like for a lost one
+        let first = SerialSetting {
+            label: CONTROLLER_LABEL.to_string(),
+            port_name: self.port.clone(),
+            baud_rate: self.baud_rate,
+            auto_detect: self.port.is_none(),
+            taken: self.taken_ports(),
+            fallback: true,
+        };
+        (InputBackend::Serial, SerialPlugin {
+            settings: std::iter::once(first).chain(others).collect(),
+        }, no_udp)
+    }
+
+    // the other players' ports, never picked for the first one
+    fn taken_ports(&self) -> Vec<String> {
+        self.controllers.iter().map(|controller| controller.port.clone()).collect()
+    }
+}
+
+/// The first available port that looks like an esp32 and isn't `taken`.
+pub fn find_controller_port(baud_rate: u32, taken: &[String]) -> Option<String> {
+    let ports = serialport::available_ports().unwrap_or_else(|e| {
+        warn!("could not list serial ports: {}", e);
+        vec![]
+    });
+    for port in ports.iter() {
+        debug!("found serial port {} ({:?})", port.port_name, port.port_type);
+    }
+
+    let (known, unknown): (Vec<_>, Vec<_>) = ports.into_iter()
+        .filter(|port| !taken.contains(&port.port_name))
+        .partition(|port| match &port.port_type {
+            serialport::SerialPortType::UsbPort(usb) => ESP32_USB_IDS.contains(&(usb.vid, usb.pid)),
+            _ => false,
+        });
+    if let Some(port) = known.first() {
+        return Some(port.port_name.clone());
+    }
+
+    unknown.into_iter()
+        .map(|port| port.port_name)
+        .find(|port_name| probe_port(port_name, baud_rate))
+}
+
+/// Splits a config file into `key = value` pairs, `#` starts a comment.
+pub fn config_entries(contents: &str) -> impl Iterator<Item = (&str, &str)> {
+    contents.lines().filter_map(|line| {
+        let line = line.split('#').next().unwrap_or_default();
+        line.split_once('=').map(|(key, value)| (key.trim(), value.trim()))
+    })
+}
+
+// listens on the port for a bit and checks if anything looks like a reading
+fn probe_port(port_name: &str, baud_rate: u32) -> bool {
+    let Ok(mut port) = serialport::new(port_name, baud_rate)
+        .timeout(Duration::from_millis(100))
+        .open() else {
+        return false;
+    };
+
+    let started = Instant::now();
+    let mut assembler = LineAssembler::default();
+    let mut buffer = [0u8; 256];
+    while started.elapsed() < PROBE_TIMEOUT {
+        if let Ok(n) = port.read(&mut buffer) {
+            let lines = assembler.push(&buffer[..n]);
+            if lines.iter().any(|line| matches!(line, ControllerLine::Sample(_))) {
+                return true;
+            }
+        }
+    }
+    false
+}
+
 impl Movement {
     fn new() -> Self {
         Self {
+            raw: 0.0,
             value: 0.0,
             direction: Dir::Neutral,
+            roll: RollFilter::default(),
+            cadence_rpm: None,
         }
     }
-    fn update(&mut self, value: f32) {
-        self.value = value;
-        let new_direction = match value {
-            x if x < -0.1 => Dir::Right,
-            x if x > 0.1 => Dir::Left,
+
+    /// Older controllers only send the gyro, which is used as the tilt like it always was.
+    pub fn apply(&mut self, sample: &ControllerSample, profile: &CalibrationProfile, frame_seconds: f32) {
+        let raw = match (sample.roll, sample.accel) {
+            (Some(roll), _) => self.roll.follow(roll, sample.gyro.y, sample.timestamp_ms, frame_seconds),
+            (None, Some(accel)) => self.roll.update(sample.gyro, accel, sample.timestamp_ms, frame_seconds),
+            (None, None) => sample.gyro.y,
+        };
+        self.update(raw, profile);
+        if sample.cadence_rpm.is_some() {
+            self.cadence_rpm = sample.cadence_rpm;
+        }
+    }
+
+    pub fn update(&mut self, raw: f32, profile: &CalibrationProfile) {
+        self.raw = raw;
+        self.value = profile.apply(raw);
+        let new_direction = match self.value {
+            x if x < 0.0 => Dir::Right,
+            x if x > 0.0 => Dir::Left,
             _ => Dir::Neutral
         };
         
         self.direction = new_direction;
     }
+
+    fn reset(&mut self) {
+        self.raw = 0.0;
+        self.value = 0.0;
+        self.direction = Dir::Neutral;
+        self.roll.reset();
+        self.cadence_rpm = None;
+    }
+}
+
+pub fn controller_connected(status: Res<ControllerStatus>) -> bool {
+    status.connected
+}
+
+/// The `Movement` of one player, while they're riding or calibrating.
+pub fn movement_of<'a>(movement_q: &'a Query<(&Movement, &PlayerId)>, player: PlayerId) -> Option<&'a Movement> {
+    movement_q.iter()
+        .find(|(_, id)| **id == player)
+        .map(|(movement, _)| movement)
 }
 
-fn init_movement(mut commands: Commands) {
-    commands.spawn((
-        Movement::new(),
-        Name::new("Movement")
+fn track_connection(
+    mut connection_events: EventReader<SerialConnectionEvent>,
+    mut udp_events: EventReader<UdpConnectionEvent>,
+    riders: Res<Riders>,
+    mut backend: ResMut<InputBackend>,
+    mut status: ResMut<ControllerStatus>,
+    mut serial_lines: ResMut<SerialLines>,
+    mut movement_q: Query<(&mut Movement, &PlayerId)>,
+) {
+    let serial_events: Vec<_> = connection_events.iter().collect();
+    // nothing plugged in at startup, the keyboard does instead of waiting forever
+    let gave_up = serial_events.iter().any(|event| matches!(
+        event,
+        SerialConnectionEvent::Unavailable { label } if riders.player(label) == Some(PlayerId::FIRST)
     ));
+    if gave_up && *backend == InputBackend::Serial {
+        warn!("no controller found, falling back to keyboard");
+        *backend = InputBackend::Keyboard;
+        status.connected = true;
+    }
+
+    // the label of the controller and whether it went away,
+    // a udp controller is read like the serial one
+    let changes = serial_events.into_iter()
+        .filter_map(|event| match event {
+            SerialConnectionEvent::Connected { label, .. } => Some((label.as_str(), false)),
+            SerialConnectionEvent::Disconnected { label } => Some((label.as_str(), true)),
+            SerialConnectionEvent::Unavailable { .. } => None,
+        })
+        .chain(udp_events.iter().map(|event| match event {
+            UdpConnectionEvent::Connected { .. } => (CONTROLLER_LABEL, false),
+            UdpConnectionEvent::Disconnected => (CONTROLLER_LABEL, true),
+        }));
+
+    // only the first player's controller pauses the ride
+    for (label, disconnected) in changes {
+        let player = riders.player(label);
+        if disconnected {
+            // whatever was half received is gone for good
+            serial_lines.0.remove(label);
+            // don't keep steering with the last reading
+            for (mut movement, id) in movement_q.iter_mut() {
+                if Some(*id) == player {
+                    movement.reset();
+                }
+            }
+        }
+        if player == Some(PlayerId::FIRST) {
+            status.connected = !disconnected;
+        }
+    }
+}
+
+fn update_disconnected_overlay(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    status: Res<ControllerStatus>,
+    overlay_q: Query<Entity, With<DisconnectedOverlay>>,
+) {
+    match (status.connected, overlay_q.get_single()) {
+        (true, Ok(entity)) => commands.entity(entity).despawn_recursive(),
+        (false, Err(_)) => {
+            commands.spawn((
+                NodeBundle {
+                    style: Style {
+                        position_type: PositionType::Absolute,
+                        width: Val::Percent(100.0),
+                        height: Val::Percent(100.0),
+                        justify_content: JustifyContent::Center,
+                        align_items: AlignItems::Center,
+                        ..default()
+                    },
+                    background_color: Color::rgba(0.0, 0.0, 0.0, 0.6).into(),
+                    ..default()
+                },
+                DisconnectedOverlay,
+                Name::new("Disconnected_Overlay"),
+            )).with_children(|commands| {
+                commands.spawn(TextBundle {
+                    text: Text::from_section("Waiting for the controller to connect", TextStyle {
+                        font: asset_server.load("fonts/Gorditas-Bold.ttf"),
+                        font_size: 52.0,
+                        color: Color::WHITE,
+                    }),
+                    ..default()
+                });
+            });
+        },
+        _ => {}
+    }
+}
+
+fn rm_disconnected_overlay(
+    mut commands: Commands,
+    overlay_q: Query<Entity, With<DisconnectedOverlay>>,
+) {
+    for entity in overlay_q.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+fn init_movement(
+    mut commands: Commands,
+    riders: Res<Riders>,
+) {
+    for rider in riders.0.iter() {
+        commands.spawn((
+            Movement::new(),
+            rider.player,
+            Name::new(format!("Movement_{}", rider.label))
+        ));
+    }
 }
 
 fn rm_movement(
     mut commands: Commands, 
-    movement_q: Query<(Entity, &Movement)>
+    movement_q: Query<Entity, With<Movement>>
 ) {
-    let (entity, _movement) = movement_q.single();
-    commands.entity(entity).despawn_recursive();
+    for entity in movement_q.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
 }
 
 fn read_serial(
     mut ev_serial: EventReader<SerialReadEvent>,
-    mut movement_q: Query<&mut Movement>,
+    riders: Res<Riders>,
+    mut serial_lines: ResMut<SerialLines>,
+    mut sample_events: EventWriter<ControllerSampleEvent>,
 ) {
-    let mut movement = movement_q.single_mut();
-    // you can get label of the port and received data buffer from `SerialReadEvent`
+    // the label says whose handlebar it is
     for SerialReadEvent(label, buffer) in ev_serial.iter() {
-        let s = String::from_utf8(buffer.clone()).unwrap();
-        
-        let new_val = s.trim().parse::<f32>().unwrap_or_default();
-        //println!("received packet from {label}: {s}, ({new_val})");
-        movement.update(new_val);
+        let Some(player) = riders.player(label) else {
+            continue;
+        };
+        receive_lines(label, player, buffer, &mut serial_lines, &mut sample_events);
+    }
+}
+
+// datagrams hold whole lines, but going through the same assembler keeps
+// the hello and the dropped sample counts working
+fn read_udp(
+    mut udp_events: EventReader<UdpReadEvent>,
+    mut serial_lines: ResMut<SerialLines>,
+    mut sample_events: EventWriter<ControllerSampleEvent>,
+) {
+    for UdpReadEvent(_, buffer) in udp_events.iter() {
+        receive_lines(CONTROLLER_LABEL, PlayerId::FIRST, buffer, &mut serial_lines, &mut sample_events);
+    }
+}
+
+fn receive_lines(
+    label: &str,
+    player: PlayerId,
+    buffer: &[u8],
+    serial_lines: &mut SerialLines,
+    sample_events: &mut EventWriter<ControllerSampleEvent>,
+) {
+    let assembler = serial_lines.0.entry(label.to_string()).or_default();
+    for line in assembler.push(buffer) {
+        match line {
+            ControllerLine::Sample(sample) => sample_events.send(ControllerSampleEvent(player, sample)),
+            ControllerLine::Hello(hello) => match hello.rate_hz {
+                Some(rate) => info!("controller {} is {:?} with protocol {} at {} Hz", label, hello.device, hello.version, rate),
+                None => info!("controller {} is {:?} with protocol {}", label, hello.device, hello.version),
+            },
+            ControllerLine::Status(message) => info!("controller {}: {}", label, message),
+        }
+    }
+}
+
+pub fn apply_samples(
+    mut sample_events: EventReader<ControllerSampleEvent>,
+    backend: Res<InputBackend>,
+    profile: Res<CalibrationProfile>,
+    mut movement_q: Query<(&mut Movement, &PlayerId)>,
+    time: Res<Time>,
+) {
+    // the other players are always on a handlebar, and share its calibration for now
+    let first_profile = backend.profile(&profile);
+    let others_profile = InputBackend::Serial.profile(&profile);
+
+    // every reading goes through, the roll estimate integrates over all of them
+    for ControllerSampleEvent(player, sample) in sample_events.iter() {
+        let profile = if *player == PlayerId::FIRST { &first_profile } else { &others_profile };
+        for (mut movement, id) in movement_q.iter_mut() {
+            if id == player {
+                movement.apply(sample, profile, time.delta_seconds());
+            }
+        }
     }
 }
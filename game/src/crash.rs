@@ -0,0 +1,154 @@
+use std::f32::consts::{FRAC_PI_2, TAU};
+
+use crate::*;
+
+// degrees, leaning further than this the bike is going over
+pub const CRASH_LEAN: f32 = 40.0;
+// seconds past `CRASH_LEAN` before it can't be caught any more
+pub const CRASH_AFTER: f32 = 1.0;
+// seconds of falling and tumbling before the ride is over
+pub const CRASH_SECONDS: f32 = 2.5;
+// seconds for the bike to hit the ground, then the view tumbles for as long again
+const FALL_SECONDS: f32 = 0.6;
+// how high the rider's eyes end up lying in the grass
+const GROUND_EYE_HEIGHT: f32 = 0.15;
+// metres per second the parrots take off at
+const PARROT_FLIGHT: f32 = 1.5;
+
+/// Something brought the bike down, the fall plays out before the ride ends.
+#[derive(Event, Debug, Clone, Copy, PartialEq)]
+pub struct CrashEvent(pub GameOverReason);
+
+/// The bike is going down. Riding stops until the fall has played out.
+#[derive(Resource, Debug)]
+pub struct Crash {
+    pub reason: GameOverReason,
+    // 1.0 falling to the left, -1.0 to the right
+    side: f32,
+    // where the bike was when it started going over
+    from: Transform,
+    timer: Timer,
+}
+
+/// A parrot leaving after the crash.
+#[derive(Component, Debug)]
+struct FlyingOff {
+    velocity: Vec3,
+}
+
+/// Ends the ride when the bike goes over, with the rider falling to the
+/// ground and the parrots flying off.
+pub struct CrashPlugin;
+
+impl Plugin for CrashPlugin {
+    fn build(&self, app: &mut App) {
+        app
+        .add_event::<CrashEvent>()
+        .add_systems(OnEnter(GameState::Gameplay), reset_crash)
+        .add_systems(Update, tip_over
+            .in_set(PlayerSet)
+            .run_if(in_state(GameState::Gameplay))
+            .run_if(controller_connected))
+        .add_systems(Update, start_crash
+            .after(PlayerSet)
+            .run_if(in_state(GameState::Gameplay))
+            .run_if(not(resource_exists::<Crash>())))
+        .add_systems(Update, (crash_player, fly_off_parrots)
+            .after(start_crash)
+            .run_if(in_state(GameState::Gameplay))
+            .run_if(resource_exists::<Crash>()))
+        ;
+    }
+}
+
+/// Whether the bike is still being ridden, rather than lying in the grass.
+pub fn riding(crash: Option<Res<Crash>>) -> bool {
+    crash.is_none()
+}
+
+fn reset_crash(mut commands: Commands) {
+    commands.remove_resource::<Crash>();
+}
+
+// a moment leaning too far can still be caught, hanging there can't
+fn tip_over(
+    mut player_q: Query<&mut Player>,
+    time: Res<Time>,
+    mut crash_events: EventWriter<CrashEvent>,
+) {
+    let mut player = player_q.single_mut();
+    if player.balance.abs() > CRASH_LEAN {
+        player.tipped_for += time.delta_seconds();
+    } else {
+        player.tipped_for = 0.0;
+    }
+    if player.tipped_for > CRASH_AFTER {
+        crash_events.send(CrashEvent(GameOverReason::FellOver));
+    }
+}
+
+fn start_crash(
+    mut commands: Commands,
+    mut crash_events: EventReader<CrashEvent>,
+    player_q: Query<(&Transform, &Player)>,
+    parrots_q: Query<(Entity, &Transform), With<Parrot>>,
+) {
+    let Some(CrashEvent(reason)) = crash_events.iter().next().copied() else {
+        return;
+    };
+    info!("crashed, {:?}", reason);
+
+    let (transform, player) = player_q.single();
+    commands.insert_resource(Crash {
+        reason,
+        side: if player.balance < 0.0 { -1.0 } else { 1.0 },
+        from: *transform,
+        timer: Timer::from_seconds(CRASH_SECONDS, TimerMode::Once),
+    });
+
+    // they scatter up and away from the middle of the handlebar
+    for (entity, parrot_transform) in parrots_q.iter() {
+        let outwards = parrot_transform.translation.x * 2.0;
+        commands.entity(entity).insert(FlyingOff {
+            velocity: Vec3::new(outwards, 1.0, -0.5).normalize() * PARROT_FLIGHT,
+        });
+    }
+}
+
+fn crash_player(
+    mut crash: ResMut<Crash>,
+    mut player_q: Query<&mut Transform, With<Player>>,
+    mut camera_q: Query<&mut Transform, (With<PlayerCamera>, Without<Player>)>,
+    time: Res<Time>,
+    mut game_state: ResMut<NextState<GameState>>,
+    mut game_over_event_writer: EventWriter<GameOverEvent>,
+) {
+    crash.timer.tick(time.delta());
+    let elapsed = crash.timer.elapsed_secs();
+
+    // falling speeds up like it would, then the view rolls over once as the rider does
+    let fall = (elapsed / FALL_SECONDS).min(1.0).powi(2);
+    let tumble = 1.0 - (1.0 - ((elapsed - FALL_SECONDS) / FALL_SECONDS).clamp(0.0, 1.0)).powi(2);
+
+    let mut transform = player_q.single_mut();
+    transform.rotation = crash.from.rotation * Quat::from_rotation_z(crash.side * fall * FRAC_PI_2);
+    transform.translation.y = crash.from.translation.y
+        + (GROUND_EYE_HEIGHT - crash.from.translation.y) * fall;
+    let mut camera_transform = camera_q.single_mut();
+    camera_transform.rotation = Quat::from_rotation_x(tumble * TAU);
+
+    if crash.timer.just_finished() {
+        // none of the parrots are coming back
+        game_over_event_writer.send(GameOverEvent(0, crash.reason));
+        game_state.set(GameState::GameOver);
+    }
+}
+
+fn fly_off_parrots(
+    mut parrots_q: Query<(&mut Transform, &FlyingOff)>,
+    time: Res<Time>,
+) {
+    for (mut transform, flying) in parrots_q.iter_mut() {
+        transform.translation += flying.velocity * time.delta_seconds();
+    }
+}
@@ -0,0 +1,172 @@
+use std::collections::VecDeque;
+
+use bevy_inspector_egui::bevy_egui::{
+    egui::{self, plot::{Line, Plot, PlotPoints}},
+    EguiContexts,
+    EguiPlugin,
+};
+
+use crate::*;
+
+pub const DIAGNOSTICS_KEY: KeyCode = KeyCode::F3;
+// seconds of history the graphs scroll through
+pub const DIAGNOSTICS_HISTORY: f32 = 10.0;
+const GRAPH_HEIGHT: f32 = 70.0;
+
+/// What the controller looked like in one frame.
+#[derive(Debug, Clone, Copy, Default)]
+struct Snapshot {
+    time: f32,
+    raw: f32,
+    value: f32,
+    direction: f32,
+    balance: f32,
+    rate: f32,
+    errors: f32,
+    since_sample: f32,
+}
+
+/// The last few seconds of controller readings, for tuning a new
+/// handlebar without reading the console.
+#[derive(Resource, Debug, Default)]
+pub struct ControllerDiagnostics {
+    pub visible: bool,
+    history: VecDeque<Snapshot>,
+    // when samples came in over the last second, for the rate
+    arrivals: VecDeque<f32>,
+    last_sample: Option<f32>,
+}
+
+/// Scrolling graphs of the controller's readings and link health,
+/// toggled with F3.
+pub struct DiagnosticsPlugin;
+
+impl Plugin for DiagnosticsPlugin {
+    fn build(&self, app: &mut App) {
+        if !app.is_plugin_added::<EguiPlugin>() {
+            app.add_plugins(EguiPlugin);
+        }
+        app
+        .init_resource::<ControllerDiagnostics>()
+        .add_systems(Update, toggle_diagnostics)
+        .add_systems(Update, record_diagnostics.after(PlayerSet))
+        .add_systems(Update, show_diagnostics
+            .after(record_diagnostics)
+            .run_if(|diagnostics: Res<ControllerDiagnostics>| diagnostics.visible))
+        ;
+    }
+}
+
+fn toggle_diagnostics(
+    keys: Res<Input<KeyCode>>,
+    mut diagnostics: ResMut<ControllerDiagnostics>,
+) {
+    if keys.just_pressed(DIAGNOSTICS_KEY) {
+        diagnostics.visible = !diagnostics.visible;
+    }
+}
+
+// keeps recording while hidden, so there's something to see when it's opened
+fn record_diagnostics(
+    mut sample_events: EventReader<ControllerSampleEvent>,
+    serial_lines: Res<SerialLines>,
+    movement_q: Query<(&Movement, &PlayerId)>,
+    player_q: Query<&Player>,
+    time: Res<Time>,
+    mut diagnostics: ResMut<ControllerDiagnostics>,
+) {
+    let now = time.elapsed_seconds();
+    for _event in sample_events.iter().filter(|event| event.0 == PlayerId::FIRST) {
+        diagnostics.arrivals.push_back(now);
+        diagnostics.last_sample = Some(now);
+    }
+    while diagnostics.arrivals.front().is_some_and(|&at| now - at > 1.0) {
+        diagnostics.arrivals.pop_front();
+    }
+
+    let (raw, value, direction) = match movement_of(&movement_q, PlayerId::FIRST) {
+        Some(movement) => (movement.raw, movement.value, match movement.direction {
+            Dir::Left => 1.0,
+            Dir::Right => -1.0,
+            Dir::Neutral => 0.0,
+        }),
+        None => (0.0, 0.0, 0.0),
+    };
+    let errors: usize = serial_lines.0.values()
+        .map(|lines| lines.invalid_utf8 + lines.invalid_frames + lines.bad_checksums + lines.overlong)
+        .sum();
+
+    let snapshot = Snapshot {
+        time: now,
+        raw,
+        value,
+        direction,
+        balance: player_q.get_single().map_or(0.0, |player| player.balance),
+        rate: diagnostics.arrivals.len() as f32,
+        errors: errors as f32,
+        since_sample: diagnostics.last_sample.map_or(0.0, |at| now - at),
+    };
+    diagnostics.history.push_back(snapshot);
+    while diagnostics.history.front().is_some_and(|snapshot| now - snapshot.time > DIAGNOSTICS_HISTORY) {
+        diagnostics.history.pop_front();
+    }
+}
+
+fn show_diagnostics(
+    mut contexts: EguiContexts,
+    diagnostics: Res<ControllerDiagnostics>,
+    serial_lines: Res<SerialLines>,
+    backend: Res<InputBackend>,
+) {
+    let Some(latest) = diagnostics.history.back().copied() else {
+        return;
+    };
+
+    egui::Window::new("Controller")
+        .default_width(360.0)
+        .show(contexts.ctx_mut(), |ui| {
+            ui.label(format!("input {:?}", *backend));
+            for (label, lines) in serial_lines.0.iter() {
+                let device = lines.hello.as_ref().map_or("unknown", |hello| hello.device.as_str());
+                ui.label(format!(
+                    "{} is {}: {} lines, {} dropped, {} bad utf-8, {} bad frames, {} bad checksums, {} too long",
+                    label, device, lines.lines, lines.dropped,
+                    lines.invalid_utf8, lines.invalid_frames, lines.bad_checksums, lines.overlong,
+                ));
+            }
+
+            graph(ui, &diagnostics, "raw", latest.raw, |snapshot| snapshot.raw);
+            graph(ui, &diagnostics, "value", latest.value, |snapshot| snapshot.value);
+            graph(ui, &diagnostics, "direction", latest.direction, |snapshot| snapshot.direction);
+            graph(ui, &diagnostics, "balance", latest.balance, |snapshot| snapshot.balance);
+            graph(ui, &diagnostics, "samples/s", latest.rate, |snapshot| snapshot.rate);
+            graph(ui, &diagnostics, "parse errors", latest.errors, |snapshot| snapshot.errors);
+            graph(ui, &diagnostics, "s since sample", latest.since_sample, |snapshot| snapshot.since_sample);
+        });
+}
+
+// the newest reading is on the right at 0, older ones scroll off to the left
+fn graph(
+    ui: &mut egui::Ui,
+    diagnostics: &ControllerDiagnostics,
+    name: &str,
+    latest: f32,
+    value: impl Fn(&Snapshot) -> f32,
+) {
+    let now = diagnostics.history.back().map_or(0.0, |snapshot| snapshot.time);
+    let points: PlotPoints = diagnostics.history.iter()
+        .map(|snapshot| [(snapshot.time - now) as f64, value(snapshot) as f64])
+        .collect();
+
+    ui.label(format!("{} {:.3}", name, latest));
+    Plot::new(name)
+        .height(GRAPH_HEIGHT)
+        .include_x(-DIAGNOSTICS_HISTORY)
+        .include_x(0.0)
+        .include_y(0.0)
+        .allow_drag(false)
+        .allow_zoom(false)
+        .allow_scroll(false)
+        .show_x(false)
+        .show(ui, |plot| plot.line(Line::new(points)));
+}
@@ -0,0 +1,108 @@
+use crate::*;
+
+pub const DISTRESS_PULSE_MS: u32 = 150;
+pub const GAME_OVER_FLASHES: u32 = 3;
+
+/// Whether the status led is lit, so it's only told when that changes.
+#[derive(Resource, Debug, Default)]
+struct StatusLed {
+    on: bool,
+}
+
+/// A command for the handlebar controller, sent over whichever
+/// transport it's on.
+#[derive(Event, Debug, Clone, Copy, PartialEq)]
+pub struct ControllerCommandEvent(pub ControllerCommand);
+
+/// Sends parrot trouble back to the handlebar controller, as a buzz when a
+/// parrot gets distressed, a steady led while they're relaxed and a few
+/// blinks when the ride is over.
+pub struct FeedbackPlugin;
+
+impl Plugin for FeedbackPlugin {
+    fn build(&self, app: &mut App) {
+        app
+        .init_resource::<StatusLed>()
+        .add_event::<ControllerCommandEvent>()
+        .add_systems(Update, pulse_on_distress
+            .after(PlayerSet)
+            .run_if(in_state(GameState::Gameplay))
+            .run_if(talks_back))
+        .add_systems(Update, light_while_relaxed
+            .after(PlayerSet)
+            .run_if(in_state(GameState::Gameplay))
+            .run_if(talks_back))
+        .add_systems(OnExit(GameState::Gameplay), status_led_off
+            .run_if(talks_back))
+        .add_systems(OnEnter(GameState::GameOver), flash_on_game_over
+            .run_if(talks_back))
+        // the ride's commands go out the same frame, the state changes' ones already ran
+        .add_systems(Update, commands_to_serial
+            .after(pulse_on_distress)
+            .after(light_while_relaxed)
+            .run_if(resource_equals(InputBackend::Serial)))
+        .add_systems(Update, commands_to_udp
+            .after(pulse_on_distress)
+            .after(light_while_relaxed)
+            .run_if(resource_equals(InputBackend::Udp)))
+        ;
+    }
+}
+
+fn talks_back(backend: Res<InputBackend>) -> bool {
+    backend.talks_back()
+}
+
+fn commands_to_serial(
+    mut command_events: EventReader<ControllerCommandEvent>,
+    mut write_events: EventWriter<SerialWriteEvent>,
+) {
+    for ControllerCommandEvent(command) in command_events.iter() {
+        write_events.send(SerialWriteEvent(CONTROLLER_LABEL.to_string(), encode_command(command).into_bytes()));
+    }
+}
+
+fn commands_to_udp(
+    mut command_events: EventReader<ControllerCommandEvent>,
+    mut write_events: EventWriter<UdpWriteEvent>,
+) {
+    for ControllerCommandEvent(command) in command_events.iter() {
+        write_events.send(UdpWriteEvent(encode_command(command).into_bytes()));
+    }
+}
+
+fn pulse_on_distress(
+    mut distress_events: EventReader<DistressedParrotEvent>,
+    mut command_events: EventWriter<ControllerCommandEvent>,
+) {
+    if distress_events.iter().count() > 0 {
+        command_events.send(ControllerCommandEvent(ControllerCommand::Pulse { millis: DISTRESS_PULSE_MS }));
+    }
+}
+
+// relaxed events keep coming every frame the balance is good
+fn light_while_relaxed(
+    mut relaxed_events: EventReader<RelaxedParrotEvent>,
+    mut command_events: EventWriter<ControllerCommandEvent>,
+    mut led: ResMut<StatusLed>,
+) {
+    let relaxed = relaxed_events.iter().count() > 0;
+    if relaxed != led.on {
+        led.on = relaxed;
+        command_events.send(ControllerCommandEvent(ControllerCommand::Led { on: relaxed }));
+    }
+}
+
+fn status_led_off(
+    mut command_events: EventWriter<ControllerCommandEvent>,
+    mut led: ResMut<StatusLed>,
+) {
+    led.on = false;
+    command_events.send(ControllerCommandEvent(ControllerCommand::Led { on: false }));
+}
+
+fn flash_on_game_over(
+    mut command_events: EventWriter<ControllerCommandEvent>,
+) {
+    command_events.send(ControllerCommandEvent(ControllerCommand::Flash { times: GAME_OVER_FLASHES }));
+}
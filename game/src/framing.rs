@@ -0,0 +1,207 @@
+use std::collections::HashMap;
+
+use crate::*;
+
+// nothing the controller sends comes close to this, so a line this long is noise
+pub const MAX_LINE_LEN: usize = 256;
+
+// what the arduino sketch prints while starting up
+const STATUS_MESSAGES: [&str; 2] = [
+    "MPU6050 Found!",
+    "Failed to find MPU6050 chip",
+];
+
+/// A complete line received from the controller.
+#[derive(Debug, PartialEq)]
+pub enum ControllerLine {
+    Sample(ControllerSample),
+    Hello(ControllerHello),
+    Status(String),
+}
+
+/// Collects bytes from one port and hands out complete lines.
+/// Partial lines are kept until the rest arrives with a later read.
+#[derive(Debug, Default)]
+pub struct LineAssembler {
+    pending: Vec<u8>,
+    pub hello: Option<ControllerHello>,
+    pub last_seq: Option<u32>,
+    pub lines: usize,
+    pub invalid_utf8: usize,
+    pub invalid_frames: usize,
+    pub bad_checksums: usize,
+    pub overlong: usize,
+    /// samples missing between sequence numbers
+    pub dropped: usize,
+}
+
+/// One `LineAssembler` per serial port, keyed by the label of the port.
+#[derive(Resource, Debug, Default)]
+pub struct SerialLines(pub HashMap<String, LineAssembler>);
+
+impl LineAssembler {
+    pub fn push(&mut self, bytes: &[u8]) -> Vec<ControllerLine> {
+        let mut lines = vec![];
+
+        for &byte in bytes {
+            if byte != b'\n' {
+                if self.pending.len() < MAX_LINE_LEN {
+                    self.pending.push(byte);
+                } else if self.pending.len() == MAX_LINE_LEN {
+                    // mark it so it gets dropped once the newline shows up
+                    self.pending.push(byte);
+                    self.overlong += 1;
+                }
+                continue;
+            }
+
+            let raw = std::mem::take(&mut self.pending);
+            if raw.len() > MAX_LINE_LEN {
+                continue;
+            }
+            if let Some(line) = self.parse(&raw) {
+                self.lines += 1;
+                lines.push(line);
+            }
+        }
+
+        lines
+    }
+
+    fn parse(&mut self, raw: &[u8]) -> Option<ControllerLine> {
+        let Ok(text) = std::str::from_utf8(raw) else {
+            self.invalid_utf8 += 1;
+            return None;
+        };
+        let text = text.trim();
+        if text.is_empty() {
+            return None;
+        }
+        if STATUS_MESSAGES.contains(&text) {
+            return Some(ControllerLine::Status(text.to_string()));
+        }
+
+        match parse_frame(text) {
+            Ok(Frame::Sample(sample)) => {
+                self.track_seq(sample.seq);
+                Some(ControllerLine::Sample(sample))
+            },
+            Ok(Frame::Hello(hello)) => {
+                if hello.version > PROTOCOL_VERSION {
+                    warn!("controller speaks protocol {}, we only know {}", hello.version, PROTOCOL_VERSION);
+                }
+                // a fresh hello means the controller restarted and counts from 0 again
+                self.last_seq = None;
+                self.hello = Some(hello.clone());
+                Some(ControllerLine::Hello(hello))
+            },
+            Ok(Frame::Command(command)) => {
+                debug!("controller sent a command back {:?}", command);
+                self.invalid_frames += 1;
+                None
+            },
+            Err(ProtocolError::BadChecksum { .. }) => {
+                self.bad_checksums += 1;
+                None
+            },
+            Err(e) => {
+                debug!("dropping controller line {:?}: {}", text, e);
+                self.invalid_frames += 1;
+                None
+            }
+        }
+    }
+
+    fn track_seq(&mut self, seq: Option<u32>) {
+        let Some(seq) = seq else {
+            return;
+        };
+        if let Some(last_seq) = self.last_seq {
+            let gap = seq.wrapping_sub(last_seq);
+            // anything else is a restart or out of order, not worth counting
+            if gap > 1 && gap < u32::MAX / 2 {
+                self.dropped += (gap - 1) as usize;
+            }
+        }
+        self.last_seq = Some(seq);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn line(seq: u32) -> String {
+        encode_sample(&ControllerSample { seq: Some(seq), gyro: Vec3::new(0.0, 0.5, 0.0), ..default() }) + "\n"
+    }
+
+    fn seqs(lines: &[ControllerLine]) -> Vec<Option<u32>> {
+        lines
+            .iter()
+            .map(|line| match line {
+                ControllerLine::Sample(sample) => sample.seq,
+                other => panic!("expected a sample, got {:?}", other),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn lines_split_across_reads_are_put_back_together() {
+        let mut assembler = LineAssembler::default();
+        let bytes = line(1) + &line(2);
+        let (first, rest) = bytes.as_bytes().split_at(line(1).len() + 5);
+        assert_eq!(seqs(&assembler.push(first)), vec![Some(1)]);
+        assert_eq!(seqs(&assembler.push(rest)), vec![Some(2)]);
+        assert_eq!(assembler.lines, 2);
+    }
+
+    #[test]
+    fn overlong_lines_are_dropped() {
+        let mut assembler = LineAssembler::default();
+        let noise = vec![b'x'; MAX_LINE_LEN * 2];
+        assert!(assembler.push(&noise).is_empty());
+        assert_eq!(seqs(&assembler.push(format!("\n{}", line(3)).as_bytes())), vec![Some(3)]);
+        assert_eq!(assembler.overlong, 1);
+    }
+
+    #[test]
+    fn status_messages_come_through() {
+        let mut assembler = LineAssembler::default();
+        assert_eq!(
+            assembler.push(b"MPU6050 Found!\r\n"),
+            vec![ControllerLine::Status("MPU6050 Found!".to_string())],
+        );
+    }
+
+    #[test]
+    fn broken_lines_are_counted() {
+        let mut assembler = LineAssembler::default();
+        let good = line(1);
+        let (payload, _) = good.trim_end().rsplit_once(CHECKSUM_START).unwrap();
+        let wrong = checksum(&payload[1..]) ^ 1;
+        assert!(assembler.push(format!("{}{}{:02X}\n", payload, CHECKSUM_START, wrong).as_bytes()).is_empty());
+        assert!(assembler.push(b"\xff\xfe\n").is_empty());
+        let nonsense = format!("{}S what{}{:02X}\n", FRAME_START, CHECKSUM_START, checksum("S what"));
+        assert!(assembler.push(nonsense.as_bytes()).is_empty());
+        // blank lines aren't broken, just empty
+        assert!(assembler.push(b"\n\r\n").is_empty());
+        assert_eq!(assembler.bad_checksums, 1);
+        assert_eq!(assembler.invalid_utf8, 1);
+        assert_eq!(assembler.invalid_frames, 1);
+        assert_eq!(assembler.lines, 0);
+    }
+
+    #[test]
+    fn gaps_in_the_sequence_count_as_dropped() {
+        let mut assembler = LineAssembler::default();
+        assembler.push((line(1) + &line(2) + &line(5)).as_bytes());
+        assert_eq!(assembler.dropped, 2);
+        assert_eq!(assembler.last_seq, Some(5));
+
+        // a restarted controller counts from the start again
+        assembler.push(b"@HELLO proto=1 dev=arduino-esp32*52\n");
+        assembler.push(line(0).as_bytes());
+        assert_eq!(assembler.dropped, 2);
+        assert!(assembler.hello.is_some());
+    }
+}
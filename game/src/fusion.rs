@@ -0,0 +1,104 @@
+use std::f32::consts::PI;
+
+use crate::*;
+
+// how long the gyro is trusted before the accelerometer pulls the angle back,
+// shorter follows gravity closer but lets road bumps through
+pub const FUSION_TIME_CONSTANT: f32 = 0.5;
+// the roll that counts as a full tilt, about what fits between the handlebar stops
+pub const FULL_TILT_ROLL: f32 = 30.0 * PI / 180.0;
+// slower than this in rad/s and the handlebar is considered still
+pub const STILL_RATE: f32 = 0.05;
+// per second, how fast the gyro bias and the center follow a still handlebar
+pub const BIAS_RATE: f32 = 0.5;
+pub const RECENTER_RATE: f32 = 0.02;
+// only a handlebar this close to the center is pulled back to it,
+// holding it tilted on purpose has to keep reading as tilted
+pub const RECENTER_RANGE: f32 = 4.0 * PI / 180.0;
+// samples further apart than this are a gap, not a time step
+pub const MAX_SAMPLE_GAP: f32 = 0.25;
+
+/// Estimates how far the handlebar is rolled from the gyro and the accelerometer.
+/// The gyro is smooth but drifts, gravity is noisy but always points down,
+/// so a complementary filter mixes the two.
+#[derive(Debug, Default, Clone, PartialEq, Reflect)]
+pub struct RollFilter {
+    /// estimated roll in radians, positive to the left
+    pub roll: f32,
+    /// what the gyro reads when the handlebar isn't moving
+    pub gyro_bias: f32,
+    /// the roll the handlebar settles at when nobody's steering
+    pub center: f32,
+    initialized: bool,
+    last_timestamp_ms: Option<u32>,
+}
+
+impl RollFilter {
+    /// Feeds in a sample that has an accelerometer reading, returns the
+    /// centered roll with a full tilt at about 1.0.
+    pub fn update(&mut self, gyro: Vec3, accel: Vec3, timestamp_ms: Option<u32>, frame_seconds: f32) -> f32 {
+        // rotating about y moves gravity between z and x
+        let accel_roll = (-accel.x).atan2(accel.z);
+
+        if !self.initialized {
+            return self.start(accel_roll, timestamp_ms);
+        }
+
+        let dt = self.step_seconds(timestamp_ms, frame_seconds);
+        let rate = gyro.y - self.gyro_bias;
+
+        let alpha = FUSION_TIME_CONSTANT / (FUSION_TIME_CONSTANT + dt);
+        self.roll = alpha * (self.roll + rate * dt) + (1.0 - alpha) * accel_roll;
+
+        self.recenter(gyro.y, dt)
+    }
+
+    /// Takes the roll a controller estimated itself, only the
+    /// centering is still done here.
+    pub fn follow(&mut self, roll: f32, gyro_y: f32, timestamp_ms: Option<u32>, frame_seconds: f32) -> f32 {
+        if !self.initialized {
+            return self.start(roll, timestamp_ms);
+        }
+
+        let dt = self.step_seconds(timestamp_ms, frame_seconds);
+        self.roll = roll;
+
+        self.recenter(gyro_y, dt)
+    }
+
+    pub fn reset(&mut self) {
+        *self = Self::default();
+    }
+
+    // wherever the handlebar starts counts as the center
+    fn start(&mut self, roll: f32, timestamp_ms: Option<u32>) -> f32 {
+        self.initialized = true;
+        self.roll = roll;
+        self.center = roll;
+        self.last_timestamp_ms = timestamp_ms;
+        0.0
+    }
+
+    fn recenter(&mut self, gyro_y: f32, dt: f32) -> f32 {
+        let rate = gyro_y - self.gyro_bias;
+        if rate.abs() < STILL_RATE {
+            self.gyro_bias += (gyro_y - self.gyro_bias) * (BIAS_RATE * dt).min(1.0);
+            if (self.roll - self.center).abs() < RECENTER_RANGE {
+                self.center += (self.roll - self.center) * (RECENTER_RATE * dt).min(1.0);
+            }
+        }
+
+        (self.roll - self.center) / FULL_TILT_ROLL
+    }
+
+    // the controller's clock when it sends one, so a replay or a late batch of
+    // samples integrates the same way, otherwise the frame we got it in
+    fn step_seconds(&mut self, timestamp_ms: Option<u32>, frame_seconds: f32) -> f32 {
+        let dt = match (self.last_timestamp_ms, timestamp_ms) {
+            (Some(last), Some(now)) => now.wrapping_sub(last) as f32 / 1000.0,
+            _ => frame_seconds,
+        };
+        self.last_timestamp_ms = timestamp_ms;
+        dt.clamp(0.0, MAX_SAMPLE_GAP)
+    }
+}
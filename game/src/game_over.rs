@@ -5,8 +5,23 @@ pub struct MainMenuButton;
 
 pub struct GameOverPlugin;
 
+/// The ride is over, with this many parrots left.
 #[derive(Event)]
-pub struct GameOverEvent(pub usize);
+pub struct GameOverEvent(pub usize, pub GameOverReason);
+
+/// Why the ride ended, shown on the game over screen.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum GameOverReason {
+    /// rode to the end of the path
+    #[default]
+    Finished,
+    /// every parrot lost its feathers
+    NoParrots,
+    /// leaned over too far for too long
+    FellOver,
+    /// rode off the path into the long grass or the scenery
+    OffPath,
+}
 
 impl Plugin for GameOverPlugin {
     fn build(&self, app: &mut App) {
@@ -23,16 +38,24 @@ fn spawn_game_over(
     mut commands: Commands,
     asset_server: Res<AssetServer>,
     score_q: Query<&Score>,
+    mut game_over_events: EventReader<GameOverEvent>,
 ) {
     let main_menu_button = spawn_button(&mut commands, &asset_server, "Main menu", Color::LIME_GREEN);
     commands.entity(main_menu_button).insert(MainMenuButton);
 
-    let mut score;
+    let score;
     if let Ok(_score) = score_q.get_single() {
-        score = _score.history.last().unwrap_or_else(|| &0);
+        score = _score.history.last().unwrap_or(&0);
     } else {
         score = &0;
     }
+    let reason = game_over_events.iter().last().map_or_else(GameOverReason::default, |event| event.1);
+    let message = match reason {
+        GameOverReason::Finished => format!("Your ride is over, with {} parrots left.", score),
+        GameOverReason::NoParrots => "Your ride is over, the parrots have all gone.".to_string(),
+        GameOverReason::FellOver => "You leaned too far and fell off, the parrots flew away.".to_string(),
+        GameOverReason::OffPath => "You rode off the path and crashed, the parrots flew away.".to_string(),
+    };
 
     commands.spawn((
         NodeBundle {
@@ -57,7 +80,7 @@ fn spawn_game_over(
                     max_width: Val::Percent(70.0),                   
                     ..default()
                 },
-                text: Text::from_section(format!("Your ride is over, with {} parrots left.", score), TextStyle {
+                text: Text::from_section(message, TextStyle {
                     font: asset_server.load("fonts/Gorditas-Bold.ttf"),
                     font_size: 96.0,
                     color: Color::BLACK,
@@ -0,0 +1,103 @@
+use std::str::FromStr;
+
+use crate::*;
+
+// roughly what a firm tilt of the handlebar reads as
+pub const FULL_TILT: f32 = 1.0;
+
+/// Where `Movement` gets its values from. Picked once at startup,
+/// see `ControllerPlugin`.
+#[derive(Resource, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum InputBackend {
+    #[default]
+    Serial,
+    Keyboard,
+    Gamepad,
+    /// the handlebar controller streaming over wi-fi, see `UdpPlugin`
+    Udp,
+    /// a phone's tilt from a web page, see `PhonePlugin`
+    Phone,
+    /// a recorded ride, see `ReplayPlugin`
+    Replay,
+}
+
+impl FromStr for InputBackend {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "serial" => Ok(Self::Serial),
+            "keyboard" => Ok(Self::Keyboard),
+            "gamepad" => Ok(Self::Gamepad),
+            "udp" => Ok(Self::Udp),
+            "phone" => Ok(Self::Phone),
+            "replay" => Ok(Self::Replay),
+            _ => Err(format!("unknown input backend {:?}", s)),
+        }
+    }
+}
+
+impl InputBackend {
+    /// Whether there's a handlebar on the other end to send feedback to.
+    pub fn talks_back(&self) -> bool {
+        matches!(self, Self::Serial | Self::Udp)
+    }
+
+    /// The profile for readings from this backend. Only the handlebar
+    /// needs calibrating, keys, sticks and phones are already centered.
+    pub fn profile(&self, calibration: &CalibrationProfile) -> CalibrationProfile {
+        match self {
+            Self::Serial | Self::Udp | Self::Replay => calibration.clone(),
+            Self::Keyboard | Self::Gamepad | Self::Phone => CalibrationProfile::centered(),
+        }
+    }
+}
+
+pub fn read_keyboard(
+    keys: Res<Input<KeyCode>>,
+    mut sample_events: EventWriter<ControllerSampleEvent>,
+) {
+    let mut value = 0.0;
+    if keys.any_pressed([KeyCode::Left, KeyCode::A]) {
+        value += FULL_TILT;
+    }
+    if keys.any_pressed([KeyCode::Right, KeyCode::D]) {
+        value -= FULL_TILT;
+    }
+    let mut sample = ControllerSample::from_tilt(value);
+    if keys.pressed(KeyCode::B) {
+        sample.buttons |= BUTTON_BELL;
+    }
+    sample_events.send(ControllerSampleEvent(PlayerId::FIRST, sample));
+}
+
+pub fn read_gamepad(
+    gamepads: Res<Gamepads>,
+    axes: Res<Axis<GamepadAxis>>,
+    buttons: Res<Input<GamepadButton>>,
+    button_axes: Res<Axis<GamepadButton>>,
+    mut sample_events: EventWriter<ControllerSampleEvent>,
+) {
+    let Some(gamepad) = gamepads.iter().next() else {
+        sample_events.send(ControllerSampleEvent(PlayerId::FIRST, ControllerSample::from_tilt(0.0)));
+        return;
+    };
+
+    // tilting left is positive, like the gyro reports it
+    let stick = -axes
+        .get(GamepadAxis::new(gamepad, GamepadAxisType::LeftStickX))
+        .unwrap_or_default();
+    let triggers = button_axes
+        .get(GamepadButton::new(gamepad, GamepadButtonType::LeftTrigger2))
+        .unwrap_or_default()
+        - button_axes
+        .get(GamepadButton::new(gamepad, GamepadButtonType::RightTrigger2))
+        .unwrap_or_default();
+
+    let value = if stick.abs() > triggers.abs() { stick } else { triggers };
+    let mut sample = ControllerSample::from_tilt(value * FULL_TILT);
+    if buttons.pressed(GamepadButton::new(gamepad, GamepadButtonType::South)) {
+        sample.buttons |= BUTTON_BELL;
+    }
+    sample_events.send(ControllerSampleEvent(PlayerId::FIRST, sample));
+}
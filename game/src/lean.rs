@@ -0,0 +1,114 @@
+use crate::*;
+
+// seconds per step of the lean, the same on every machine and in replays
+pub const LEAN_STEP: f32 = 1.0 / 120.0;
+// metres, a very tall bike so it falls slowly enough to catch with a handlebar
+pub const LEAN_HEIGHT: f32 = 10.0;
+// per second, the tyres and the rider soaking up the wobble
+pub const LEAN_DAMPING: f32 = 3.0;
+// riding this fast the bike stays up by itself
+pub const SELF_STABLE_SPEED: f32 = MAX_SPEED;
+// slower than this the bike gets wobbly, on top of being less steady
+pub const MIN_STABLE_SPEED: f32 = PLAYER_SPEED * 0.5;
+// how much harder gravity pulls when the bike stands still
+pub const TIP_RATE: f32 = 1.5;
+// degrees per second² for each degree per second of `steering_rate`
+pub const STEERING_TORQUE: f32 = 6.0;
+// degrees, the bike is on the ground
+pub const MAX_LEAN: f32 = 45.0;
+// degrees per second, how hard a bump can knock the bike over
+pub const DISRUPT_KICK: f32 = 90.0;
+
+/// The bike as an upside down pendulum, leaning `angle` degrees to the
+/// left and tipping further at `rate` degrees per second.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Lean {
+    pub angle: f32,
+    pub rate: f32,
+}
+
+impl Lean {
+    /// How fast the lean speeds up, in degrees per second². Gravity pulls it
+    /// over, less so the faster the bike goes and more so below
+    /// `MIN_STABLE_SPEED`, and `torque` from the rider pushes it back.
+    pub fn acceleration(&self, speed: f32, torque: f32) -> f32 {
+        let falling = (GRAVITY / LEAN_HEIGHT) * self.angle.to_radians().sin();
+        let steadying = (speed / SELF_STABLE_SPEED).clamp(0.0, 1.0).powi(2);
+        falling.to_degrees() * (1.0 - steadying) * (1.0 + tipping(speed)) - LEAN_DAMPING * self.rate + torque
+    }
+
+    /// The lean `dt` seconds later. It stops where the bike hits the ground.
+    pub fn step(self, speed: f32, torque: f32, dt: f32) -> Self {
+        let rate = self.rate + self.acceleration(speed, torque) * dt;
+        let angle = self.angle + rate * dt;
+        if angle.abs() > MAX_LEAN {
+            Self { angle: angle.clamp(-MAX_LEAN, MAX_LEAN), rate: 0.0 }
+        } else {
+            Self { angle, rate }
+        }
+    }
+}
+
+/// How much harder a slow bike is to balance, 0.0 from `MIN_STABLE_SPEED` up.
+pub fn tipping(speed: f32) -> f32 {
+    TIP_RATE * (1.0 - speed / MIN_STABLE_SPEED).max(0.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ride(mut lean: Lean, speed: f32, torque: f32, seconds: f32) -> Lean {
+        for _ in 0..(seconds / LEAN_STEP) as usize {
+            lean = lean.step(speed, torque, LEAN_STEP);
+        }
+        lean
+    }
+
+    // how far a small lean has gone after a few seconds with nobody catching it
+    fn fall(speed: f32) -> f32 {
+        ride(Lean { angle: 2.0, rate: 0.0 }, speed, 0.0, 3.0).angle
+    }
+
+    #[test]
+    fn upright_stays_upright() {
+        for speed in [0.0, PLAYER_SPEED, MAX_SPEED] {
+            assert_eq!(ride(Lean::default(), speed, 0.0, 5.0), Lean::default());
+        }
+    }
+
+    #[test]
+    fn riding_fast_enough_settles_a_wobble() {
+        let lean = ride(Lean { angle: 5.0, rate: 10.0 }, SELF_STABLE_SPEED, 0.0, 5.0);
+        assert!(lean.rate.abs() < 0.01, "still tipping at {}", lean.rate);
+        assert!(lean.angle.abs() < 10.0, "settled at {}", lean.angle);
+    }
+
+    #[test]
+    fn a_stopped_bike_falls_over_and_stays_there() {
+        assert_eq!(ride(Lean { angle: 1.0, rate: 0.0 }, 0.0, 0.0, 10.0), Lean { angle: MAX_LEAN, rate: 0.0 });
+        assert_eq!(ride(Lean { angle: -1.0, rate: 0.0 }, 0.0, 0.0, 10.0), Lean { angle: -MAX_LEAN, rate: 0.0 });
+    }
+
+    #[test]
+    fn slower_falls_further() {
+        assert!(fall(PLAYER_SPEED) > fall(MAX_SPEED * 0.9));
+        assert!(fall(MIN_STABLE_SPEED) > fall(PLAYER_SPEED));
+    }
+
+    #[test]
+    fn steering_into_the_fall_catches_it() {
+        let falling = Lean { angle: 10.0, rate: 5.0 };
+        assert!(ride(falling, PLAYER_SPEED, -STEERING_TORQUE * 20.0, 1.0).angle < falling.angle);
+        assert!(ride(falling, PLAYER_SPEED, 0.0, 1.0).angle > falling.angle);
+    }
+
+    #[test]
+    fn riding_slower_than_the_minimum_tips_over_quicker() {
+        assert_eq!(tipping(MIN_STABLE_SPEED), 0.0);
+        assert_eq!(tipping(PLAYER_SPEED), 0.0);
+        assert!(tipping(0.0) > tipping(MIN_STABLE_SPEED * 0.5));
+        assert!(fall(MIN_STABLE_SPEED * 0.5) > fall(MIN_STABLE_SPEED));
+        assert!(fall(0.0) > fall(MIN_STABLE_SPEED * 0.5));
+    }
+}
@@ -1,17 +1,277 @@
+use bevy::{
+    asset::{AssetLoader, LoadContext, LoadedAsset},
+    math::Vec3Swizzles,
+    reflect::{TypePath, TypeUuid},
+    utils::BoxedFuture,
+};
+use serde::{de::Error as _, Deserialize, Deserializer};
+
 use crate::*;
 
+pub const DEFAULT_LEVEL: &str = "levels/park.level.ron";
+// what `GameAssets` asks for the park by
+const LEVEL_KEY: &str = "level";
+
+/// Loads the park from the `GameConfig`'s `level`, a path inside `assets`.
 pub struct LevelPlugin;
 
 #[derive(Component, Debug, Reflect)]
 pub struct Level;
 
+/// A park to ride through, from a `.level.ron` file in `assets/levels`.
+/// Colors are hex like `"008000"`.
+#[derive(Debug, Clone, Deserialize, TypeUuid, TypePath)]
+#[uuid = "5f803ec1-77f1-4a10-8c3d-265e7598b105"]
+pub struct Park {
+    pub start: Start,
+    /// on the ground, `(x, z)`, the path curves through them
+    pub waypoints: Vec<Vec2>,
+    pub ride: Ride,
+    pub path: PathStyle,
+    pub floor: Floor,
+    pub light: Light,
+    #[serde(default)]
+    pub scenery: Vec<Scenery>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Start {
+    pub position: Vec3,
+    /// degrees turned to the left, the path sets off this way
+    #[serde(default)]
+    pub heading: f32,
+}
+
+/// How the park is ridden, taken from the park when it loads.
+#[derive(Resource, Debug, Clone, Deserialize, Reflect)]
+#[reflect(Resource)]
+pub struct Ride {
+    /// without a pedal sensor
+    pub speed: f32,
+    /// seconds between the bike getting knocked off balance
+    pub disrupt_every: f32,
+}
+
+impl Default for Ride {
+    fn default() -> Self {
+        Self {
+            speed: PLAYER_SPEED,
+            disrupt_every: 5.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct PathStyle {
+    /// metres, the bike is on the grass past the edge
+    pub width: f32,
+    #[serde(deserialize_with = "hex_color")]
+    pub color: Color,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Floor {
+    pub size: f32,
+    #[serde(deserialize_with = "hex_color")]
+    pub color: Color,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Light {
+    pub illuminance: f32,
+    #[serde(default)]
+    pub shadows: bool,
+    pub position: Vec3,
+    /// degrees, looking down is negative
+    pub pitch: f32,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Scenery {
+    pub name: String,
+    pub shape: SceneryShape,
+    #[serde(deserialize_with = "hex_color")]
+    pub color: Color,
+    pub position: Vec3,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+pub enum SceneryShape {
+    Cube { size: f32 },
+    Box { x: f32, y: f32, z: f32 },
+    Sphere { radius: f32 },
+}
+
+/// Scenery the bike crashes into when it wanders off the path.
+#[derive(Component, Debug, Reflect)]
+pub struct Obstacle {
+    /// metres around the middle on the ground
+    pub radius: f32,
+}
+
+impl SceneryShape {
+    fn radius(&self) -> f32 {
+        match *self {
+            Self::Cube { size } => size / 2.0,
+            Self::Box { x, z, .. } => x.max(z) / 2.0,
+            Self::Sphere { radius } => radius,
+        }
+    }
+
+    fn mesh(&self) -> Mesh {
+        match *self {
+            Self::Cube { size } => shape::Cube::new(size).into(),
+            Self::Box { x, y, z } => shape::Box::new(x, y, z).into(),
+            Self::Sphere { radius } => shape::UVSphere { radius, ..default() }.into(),
+        }
+    }
+}
+
+fn hex_color<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Color, D::Error> {
+    let hex = String::deserialize(deserializer)?;
+    Color::hex(&hex).map_err(|e| D::Error::custom(format!("bad color {:?}: {:?}", hex, e)))
+}
+
+#[derive(Default)]
+struct ParkLoader;
+
+// the park file picked at startup, loaded along with the rest of `GameAssets`
+#[derive(Debug)]
+struct ParkFile(String);
+
+impl DynamicAsset for ParkFile {
+    fn load(&self, asset_server: &AssetServer) -> Vec<HandleUntyped> {
+        vec![asset_server.load_untyped(&self.0)]
+    }
+
+    fn build(&self, world: &mut World) -> Result<DynamicAssetType, bevy::asset::Error> {
+        Ok(DynamicAssetType::Single(world.resource::<AssetServer>().get_handle_untyped(&self.0)))
+    }
+}
+
+impl AssetLoader for ParkLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), bevy::asset::Error>> {
+        Box::pin(async move {
+            let park: Park = ron::de::from_bytes(bytes)?;
+            if park.waypoints.is_empty() {
+                return Err(bevy::asset::Error::msg("a park needs at least one waypoint"));
+            }
+            load_context.set_default_asset(LoadedAsset::new(park));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["level.ron"]
+    }
+}
+
 impl Plugin for LevelPlugin {
     fn build(&self, app: &mut App) {
+        let level = app.world.get_resource::<GameConfig>()
+            .map_or_else(|| DEFAULT_LEVEL.to_string(), |config| config.level.clone());
+        info!("riding {}", level);
+        app
+        .init_resource::<DynamicAssets>()
+        .world.resource_mut::<DynamicAssets>()
+        .register_asset(LEVEL_KEY, Box::new(ParkFile(level)));
+
         app
         .register_type::<Level>()
+        .register_type::<Ride>()
+        .register_type::<Obstacle>()
+        .add_asset::<Park>()
+        .init_asset_loader::<ParkLoader>()
+        .init_resource::<Ride>()
+        .add_systems(OnExit(GameState::Loading), apply_park)
         .add_systems(OnEnter(GameState::Gameplay), spawn_basic_scene)
-        .add_systems(OnExit(GameState::Gameplay), rm_basic_scene);
+        .add_systems(OnExit(GameState::Gameplay), rm_basic_scene)
+        .add_systems(Update, reload_park.run_if(not(in_state(GameState::Loading))))
+        .add_systems(Update, rebuild_park.run_if(in_state(GameState::Gameplay)))
+        ;
+    }
+}
+
+// the path and ride settings are read when the ride starts, the scenery is built then
+fn apply_park(
+    mut commands: Commands,
+    game_assets: Res<GameAssets>,
+    parks: Res<Assets<Park>>,
+) {
+    if let Some(park) = parks.get(&game_assets.park) {
+        insert_park(&mut commands, park);
+    }
+}
+
+fn insert_park(commands: &mut Commands, park: &Park) {
+    commands.insert_resource(PlayerPath {
+        start: Transform::from_translation(park.start.position)
+            .with_rotation(Quat::from_rotation_y(park.start.heading.to_radians())),
+        spline: park.spline(),
+        width: park.path.width,
+    });
+    commands.insert_resource(park.ride.clone());
+}
+
+impl Park {
+    fn spline(&self) -> RidePath {
+        RidePath::new(self.start.position.xz(), self.start.heading.to_radians(), &self.waypoints)
+    }
+}
+
+// saving the park file while the game runs shows the changes right away,
+// the bike keeps riding from where it is
+fn reload_park(
+    mut commands: Commands,
+    mut park_events: EventReader<AssetEvent<Park>>,
+    game_assets: Res<GameAssets>,
+    parks: Res<Assets<Park>>,
+    mut player_q: Query<&mut Player>,
+) {
+    if !park_modified(&mut park_events, &game_assets) {
+        return;
+    }
+    info!("park changed, reloading");
+    let Some(park) = parks.get(&game_assets.park) else {
+        return;
+    };
+    insert_park(&mut commands, park);
+    // the bumps keep coming, as often as the park says now
+    for mut player in player_q.iter_mut() {
+        player.disrupt_timer.set_duration(Duration::from_secs_f32(park.ride.disrupt_every));
+    }
+}
+
+fn rebuild_park(
+    mut commands: Commands,
+    mut park_events: EventReader<AssetEvent<Park>>,
+    game_assets: Res<GameAssets>,
+    parks: Res<Assets<Park>>,
+    level_q: Query<Entity, With<Level>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    if !park_modified(&mut park_events, &game_assets) {
+        return;
+    }
+    let Some(park) = parks.get(&game_assets.park) else {
+        return;
+    };
+    for entity in level_q.iter() {
+        commands.entity(entity).despawn_recursive();
     }
+    spawn_park(&mut commands, &mut meshes, &mut materials, park);
+}
+
+fn park_modified(park_events: &mut EventReader<AssetEvent<Park>>, game_assets: &GameAssets) -> bool {
+    park_events.iter().any(|event| matches!(
+        event,
+        AssetEvent::Modified { handle } if *handle == game_assets.park
+    ))
 }
 
 fn rm_basic_scene(
@@ -27,24 +287,43 @@ fn spawn_basic_scene(
     mut commands: Commands,
     mut meshes: ResMut<Assets<Mesh>>,
     mut materials: ResMut<Assets<StandardMaterial>>,
-    _game_assets: Res<GameAssets>,
-    mut rng_q: Query<&mut EntropyComponent<ChaCha8Rng>>,
+    game_assets: Res<GameAssets>,
+    parks: Res<Assets<Park>>,
+) {
+    if let Some(park) = parks.get(&game_assets.park) {
+        spawn_park(&mut commands, &mut meshes, &mut materials, park);
+    }
+}
+
+fn spawn_park(
+    commands: &mut Commands,
+    meshes: &mut Assets<Mesh>,
+    materials: &mut Assets<StandardMaterial>,
+    park: &Park,
 ) {
     let floor = (PbrBundle {
-        mesh: meshes.add(Mesh::from(shape::Plane::from_size(30.0))),
-        material: materials.add(Color::YELLOW_GREEN.into()),
+        mesh: meshes.add(Mesh::from(shape::Plane::from_size(park.floor.size))),
+        material: materials.add(park.floor.color.into()),
         ..default()
     }, Level, Name::new("Floor"));
 
+    // just above the floor so they don't flicker
+    let path = (PbrBundle {
+        mesh: meshes.add(park.spline().mesh(park.path.width)),
+        material: materials.add(park.path.color.into()),
+        transform: Transform::from_xyz(0.0, 0.005, 0.0),
+        ..default()
+    }, Level, Name::new("Path"));
+
     let dir_light = (DirectionalLightBundle {
         directional_light: DirectionalLight {
-            shadows_enabled: true,
-            illuminance: 5000.0,
+            shadows_enabled: park.light.shadows,
+            illuminance: park.light.illuminance,
             ..default()
         },
         transform: Transform {
-            translation: Vec3::new(5.0, 4.0, 0.5),
-            rotation: Quat::from_rotation_x(-PI / 4.),
+            translation: park.light.position,
+            rotation: Quat::from_rotation_x(park.light.pitch.to_radians()),
             ..default()
         },
         cascade_shadow_config: CascadeShadowConfigBuilder {
@@ -55,24 +334,19 @@ fn spawn_basic_scene(
         ..default()
     }, Level, Name::new("DirectionalLight"));
 
-    
-    let mut create_cube = |size: f32, color: Color, xyz: (f32, f32, f32), name: String | -> (PbrBundle, Level, Name) {
-        (PbrBundle {
-            mesh: meshes.add(Mesh::from(shape::Cube::new(size))),
-            material: materials.add(color.into()),
-            transform: Transform::from_xyz(xyz.0, xyz.1, xyz.2),
+    for scenery in park.scenery.iter() {
+        commands.spawn((PbrBundle {
+            mesh: meshes.add(scenery.shape.mesh()),
+            material: materials.add(scenery.color.into()),
+            transform: Transform::from_translation(scenery.position),
             ..default()
         },
+        Obstacle { radius: scenery.shape.radius() },
         Level,
-        Name::new(name))
-    };
-    commands.spawn(create_cube(0.7, Color::DARK_GREEN, (5.0, 0.35, 4.4), "Cube 1".to_string()));
-    commands.spawn(create_cube(0.6, Color::GREEN, (2.0, 0.3, 7.4), "Cube 2".to_string()));
-    commands.spawn(create_cube(0.8, Color::DARK_GREEN, (-3.0, 0.4, 0.5), "Cube 3".to_string()));
-    commands.spawn(create_cube(0.3, Color::BLUE, (-8.0, 0.15, 7.5), "Cube 4".to_string()));
-    commands.spawn(create_cube(0.5, Color::DARK_GREEN, (4.2, 0.25, -6.5), "Cube 5".to_string()));
-    //commands.spawn(create_cube(0.5, Color::GREEN, (0.0, 0.25, 0.0), "Cube 6".to_string()));
+        Name::new(scenery.name.clone())));
+    }
 
     commands.spawn(dir_light);
     commands.spawn(floor);
+    commands.spawn(path);
 }
@@ -1,27 +1,62 @@
-use bevy::{prelude::*, pbr::{CascadeShadowConfigBuilder}};
-use bevy_inspector_egui::{quick::WorldInspectorPlugin};
+use bevy::{prelude::*, asset::ChangeWatcher, pbr::{CascadeShadowConfigBuilder}};
 use bevy_sprite3d::*;
 use bevy_rand::prelude::*;
 use bevy_prng::ChaCha8Rng;
 use rand::prelude::Rng;
 use bevy_asset_loader::prelude::*;
 use bevy_mod_reqwest::*;
-use bevy_serial::{SerialPlugin, SerialReadEvent};
 
 use std::f32::consts::PI;
+use std::time::Duration;
 
+mod config;
 mod main_menu;
 mod game_over;
 mod player;
 mod parrot;
 mod controller;
+mod serial;
+mod calibration;
+mod framing;
+mod protocol;
+mod input;
+mod steering;
+mod replay;
+mod fusion;
+mod feedback;
+mod udp;
+mod phone;
+mod pedalling;
+mod bell;
+mod crash;
+mod diagnostics;
 mod level;
+mod path;
+mod lean;
 mod score;
 
+pub use config::*;
 pub use player::*;
 pub use parrot::*;
 pub use controller::*;
+pub use serial::*;
+pub use calibration::*;
+pub use framing::*;
+pub use protocol::*;
+pub use input::*;
+pub use steering::*;
+pub use replay::*;
+pub use fusion::*;
+pub use feedback::*;
+pub use udp::*;
+pub use phone::*;
+pub use pedalling::*;
+pub use bell::*;
+pub use crash::*;
+pub use diagnostics::*;
 pub use level::*;
+pub use path::*;
+pub use lean::*;
 pub use main_menu::*;
 pub use game_over::*;
 pub use score::*;
@@ -41,6 +76,10 @@ fn main() {
                     ..default()
                 }),
                 ..default()
+            }).set(AssetPlugin {
+                // levels can be edited while riding them
+                watch_for_changes: ChangeWatcher::with_delay(Duration::from_millis(200)),
+                ..default()
             })
         )
         .add_state::<GameState>()
@@ -49,15 +88,20 @@ fn main() {
                 .continue_to_state(GameState::MainMenu)
         )
         .add_collection_to_loading_state::<_, GameAssets>(GameState::Loading)
+        .add_plugins(ConfigPlugin)
         .add_plugins(ReqwestPlugin)
         .add_plugins(Sprite3dPlugin)
         .add_plugins(EntropyPlugin::<ChaCha8Rng>::default(),)
-        //.add_plugins(WorldInspectorPlugin::new())
+        //.add_plugins(bevy_inspector_egui::quick::WorldInspectorPlugin::new())
         .add_plugins(MainMenuPlugin)
         .add_plugins(GameOverPlugin)
         .add_plugins(PlayerPlugin)
         .add_plugins(ParrotPlugin)
-        .add_plugins(ControllerPlugin)
+        .add_plugins(BellPlugin)
+        .add_plugins(CrashPlugin)
+        .add_plugins(ControllerPlugin::new(ControllerConfig::load()))
+        .add_plugins(CalibrationPlugin)
+        .add_plugins(DiagnosticsPlugin)
         .add_plugins(LevelPlugin)
         .add_plugins(ScorePlugin)
         .run();
@@ -81,6 +125,10 @@ pub struct GameAssets {
     rotation_indicator: Handle<Image>,
     #[asset(path = "handle_indicator.png")]
     handle_indicator: Handle<Image>,
+    #[asset(path = "bell.wav")]
+    bell_sound: Handle<AudioSource>,
+    #[asset(key = "level")]
+    park: Handle<Park>,
 }
 
 
@@ -89,6 +137,7 @@ pub enum GameState {
     #[default]
     Loading,
     MainMenu,
+    Calibration,
     Gameplay,
     GameOver,
 }
\ No newline at end of file
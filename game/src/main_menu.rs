@@ -8,6 +8,9 @@ pub struct MenuUIRoot;
 #[derive(Component)]
 pub struct StartButton;
 
+#[derive(Component)]
+pub struct CalibrateButton;
+
 #[derive(Component)]
 pub struct QuitButton;
 
@@ -22,6 +25,7 @@ impl Plugin for MainMenuPlugin {
             .add_systems(OnEnter(GameState::MainMenu), spawn_main_menu)
             .add_systems(OnExit(GameState::MainMenu), despawn_menu_camera)
             .add_systems(Update, start_button_clicked.run_if(in_state(GameState::MainMenu)))
+            .add_systems(Update, calibrate_button_clicked.run_if(in_state(GameState::MainMenu)))
             .add_systems(Update, quit_button_clicked.run_if(in_state(GameState::MainMenu)));
     }
 }
@@ -53,6 +57,9 @@ fn spawn_main_menu(
     let start_button = spawn_button(&mut commands, &asset_server, "Start riding", Color::LIME_GREEN);
     commands.entity(start_button).insert(StartButton);
 
+    let calibrate_button = spawn_button(&mut commands, &asset_server, "Calibrate", Color::NONE);
+    commands.entity(calibrate_button).insert(CalibrateButton);
+
     let quit_button = spawn_button(&mut commands, &asset_server, "Exit game", Color::NONE);
     commands.entity(quit_button).insert(QuitButton);
 
@@ -90,6 +97,7 @@ fn spawn_main_menu(
         ));
     })
     .add_child(start_button)
+    .add_child(calibrate_button)
     .add_child(quit_button);
 }
 
@@ -150,6 +158,23 @@ fn start_button_clicked(
     }
 }
 
+fn calibrate_button_clicked(
+  mut commands: Commands,
+  interactions: Query<&Interaction, (With<CalibrateButton>, Changed<Interaction>)>,
+  menu_root: Query<Entity, With<MenuUIRoot>>,
+  mut game_state: ResMut<NextState<GameState>>,
+  mut mouse_input: ResMut<Input<MouseButton>>,
+) {
+    for interaction in &interactions {
+        if matches!(interaction, Interaction::Pressed) {
+            let root_entity = menu_root.single();
+            commands.entity(root_entity).despawn_recursive();
+            game_state.set(GameState::Calibration);
+            mouse_input.clear();
+        }
+    }
+}
+
 fn quit_button_clicked(
     _commands: Commands,
     interactions: Query<&Interaction, (With<QuitButton>, Changed<Interaction>)>,
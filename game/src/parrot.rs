@@ -41,12 +41,20 @@ impl Plugin for ParrotPlugin {
         .register_type::<Parrot>()
         .add_event::<DistressedParrotEvent>()
         .add_event::<RelaxedParrotEvent>()
-        .add_systems(Update, distress_parrots.run_if(in_state(GameState::Gameplay)))
+        .add_systems(Update, distress_parrots
+            .after(PlayerSet)
+            .run_if(in_state(GameState::Gameplay)))
         //.add_systems(Update, spawn_bangs.run_if(in_state(GameState::Gameplay)))
         .add_systems(Update, update_parrot_sprites.run_if(in_state(GameState::Gameplay)))
         .add_systems(Update, despawn_bangs.run_if(in_state(GameState::Gameplay)))
-        .add_systems(Update, relax_parrots.run_if(in_state(GameState::Gameplay)))
-        .add_systems(Update, check_parrot_health.run_if(in_state(GameState::Gameplay)))
+        .add_systems(Update, relax_parrots
+            .after(distress_parrots)
+            .run_if(in_state(GameState::Gameplay)))
+        .add_systems(Update, check_parrot_health
+            .after(relax_parrots)
+            .run_if(in_state(GameState::Gameplay))
+            .run_if(controller_connected)
+            .run_if(riding))
         .add_systems(Update, check_parrots_left.run_if(in_state(GameState::Gameplay)));
     }
 }
@@ -117,7 +125,7 @@ fn distress_parrots(
 
         if max_parrots != 1 {
             let mut rng = rng_q.single_mut();
-            who = rng.gen_range(0..max_parrots) as usize;    
+            who = rng.gen_range(0..max_parrots);    
         }
         
         for _event in distress_events.iter() {
@@ -146,10 +154,12 @@ fn relax_parrots(
 fn check_parrot_health(
     mut commands: Commands,
     mut parrots: Query<(Entity, &mut Parrot)>,
+    bell: Res<Bell>,
     time: Res<Time>,
 ) {
     for (entity, mut parrot) in &mut parrots.iter_mut() {
-        if parrot.is_distressed {
+        // the bell takes their mind off it for a bit
+        if parrot.is_distressed && !bell.calming() {
             parrot.distress_timer.tick(time.delta());
 
             if parrot.distress_timer.just_finished() {
@@ -170,7 +180,7 @@ fn check_parrots_left(
     mut game_over_event_writer: EventWriter<GameOverEvent>
 ) {
     if parrots_q.is_empty() {
-        game_over_event_writer.send(GameOverEvent(0));
+        game_over_event_writer.send(GameOverEvent(0, GameOverReason::NoParrots));
         game_state.set(GameState::GameOver);
     }
 }
@@ -0,0 +1,266 @@
+use std::{f32::consts::TAU, str::FromStr};
+
+use bevy::render::{mesh::Indices, render_resource::PrimitiveTopology};
+
+use crate::*;
+
+// points sampled along each piece of the curve, enough that riding
+// the samples in straight lines looks round
+pub const SPLINE_SAMPLES: usize = 24;
+// waypoints closer than this are the same point
+const SAME_POINT: f32 = 0.01;
+// how far the bike slides sideways for each metre ridden, leaning all the way over
+pub const DRIFT_GAIN: f32 = 1.0;
+// speed on the grass next to the path
+pub const GRASS_SPEED: f32 = 0.5;
+// metres off the edge of the path before the bike falls over in the long grass
+pub const GRASS_FALL: f32 = 1.5;
+// how close the middle of the bike gets to scenery before hitting it
+pub const BIKE_RADIUS: f32 = 0.2;
+
+/// Whether the bike stays on the path whatever the balance does.
+#[derive(Resource, Debug, Default, Clone, Copy, PartialEq, Eq, Reflect)]
+#[reflect(Resource)]
+pub enum LineMode {
+    /// the bike follows the path by itself
+    #[default]
+    Rails,
+    /// leaning steers the bike off the path, counter-steer to get back
+    Lean,
+}
+
+impl FromStr for LineMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "rails" => Ok(Self::Rails),
+            "lean" => Ok(Self::Lean),
+            _ => Err(format!("unknown line {:?}", s)),
+        }
+    }
+}
+
+/// The ride's path as a smooth curve through the waypoints, a centripetal
+/// Catmull-Rom spline so sharp turns don't loop over themselves. It's
+/// sampled once up front, which lets the bike ride it by distance and
+/// keep the same speed through the bends.
+#[derive(Debug, Clone, Default)]
+pub struct RidePath {
+    points: Vec<Vec2>,
+    // distance along the path to each point
+    distances: Vec<f32>,
+}
+
+impl RidePath {
+    /// A path from `start`, setting off facing `heading`, through `waypoints`.
+    pub fn new(start: Vec2, heading: f32, waypoints: &[Vec2]) -> Self {
+        let mut controls = vec![start];
+        for &waypoint in waypoints {
+            if controls.last().is_some_and(|last| last.distance(waypoint) > SAME_POINT) {
+                controls.push(waypoint);
+            }
+        }
+        if controls.len() < 2 {
+            return Self { points: vec![start], distances: vec![0.0] };
+        }
+
+        // made up points before and after the ends shape how the curve
+        // leaves the start and comes into the last waypoint
+        let first_step = controls[0].distance(controls[1]);
+        let before = start - forward(heading) * first_step;
+        let last = controls[controls.len() - 1];
+        let after = last + (last - controls[controls.len() - 2]);
+        controls.insert(0, before);
+        controls.push(after);
+
+        let mut points = vec![start];
+        for window in controls.windows(4) {
+            for i in 1..=SPLINE_SAMPLES {
+                let t = i as f32 / SPLINE_SAMPLES as f32;
+                points.push(catmull_rom(window[0], window[1], window[2], window[3], t));
+            }
+        }
+
+        let mut distances = Vec::with_capacity(points.len());
+        let mut travelled = 0.0;
+        for (i, point) in points.iter().enumerate() {
+            if i > 0 {
+                travelled += points[i - 1].distance(*point);
+            }
+            distances.push(travelled);
+        }
+        Self { points, distances }
+    }
+
+    /// How long the whole path is.
+    pub fn length(&self) -> f32 {
+        self.distances.last().copied().unwrap_or(0.0)
+    }
+
+    /// Where on the ground the bike is after riding `distance`.
+    pub fn position(&self, distance: f32) -> Vec2 {
+        let (i, t) = self.locate(distance);
+        match self.points.get(i + 1) {
+            Some(next) => self.points[i].lerp(*next, t),
+            None => self.points[i],
+        }
+    }
+
+    /// Which way the path goes after riding `distance`, normalized.
+    pub fn direction(&self, distance: f32) -> Vec2 {
+        let (i, _) = self.locate(distance);
+        let i = i.min(self.points.len().saturating_sub(2));
+        match self.points.get(i + 1) {
+            Some(next) => (*next - self.points[i]).normalize_or_zero(),
+            None => Vec2::ZERO,
+        }
+    }
+
+    /// A flat strip along the path `width` wide, to lay on the ground.
+    pub fn mesh(&self, width: f32) -> Mesh {
+        let mut positions = Vec::with_capacity(self.points.len() * 2);
+        let mut uvs = Vec::with_capacity(self.points.len() * 2);
+        for (i, point) in self.points.iter().enumerate() {
+            let side = left_of(self.direction(self.distances[i])) * width / 2.0;
+            for (edge, u) in [(*point + side, 0.0), (*point - side, 1.0)] {
+                positions.push([edge.x, 0.0, edge.y]);
+                uvs.push([u, self.distances[i] / width]);
+            }
+        }
+
+        let mut indices = vec![];
+        for i in 0..self.points.len().saturating_sub(1) as u32 {
+            let (left, right, next_left, next_right) = (i * 2, i * 2 + 1, i * 2 + 2, i * 2 + 3);
+            indices.extend([left, right, next_left, right, next_right, next_left]);
+        }
+
+        let mut mesh = Mesh::new(PrimitiveTopology::TriangleList);
+        mesh.insert_attribute(Mesh::ATTRIBUTE_NORMAL, vec![[0.0, 1.0, 0.0]; positions.len()]);
+        mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, positions);
+        mesh.insert_attribute(Mesh::ATTRIBUTE_UV_0, uvs);
+        mesh.set_indices(Some(Indices::U32(indices)));
+        mesh
+    }
+
+    // the sample the distance falls after, and how far along to the next one
+    fn locate(&self, distance: f32) -> (usize, f32) {
+        let i = self.distances.partition_point(|&d| d <= distance).saturating_sub(1);
+        match self.distances.get(i + 1) {
+            Some(next) if *next > self.distances[i] => {
+                (i, ((distance - self.distances[i]) / (next - self.distances[i])).clamp(0.0, 1.0))
+            },
+            _ => (i, 0.0),
+        }
+    }
+}
+
+// the curve between p1 and p2, with knots spaced by the square root of the
+// distance between points, the Barry and Goldman way
+fn catmull_rom(p0: Vec2, p1: Vec2, p2: Vec2, p3: Vec2, t: f32) -> Vec2 {
+    let knot = |a: Vec2, b: Vec2| a.distance(b).sqrt().max(f32::EPSILON);
+    let t0 = 0.0;
+    let t1 = t0 + knot(p0, p1);
+    let t2 = t1 + knot(p1, p2);
+    let t3 = t2 + knot(p2, p3);
+    let t = t1 + (t2 - t1) * t;
+
+    let a1 = p0 * ((t1 - t) / (t1 - t0)) + p1 * ((t - t0) / (t1 - t0));
+    let a2 = p1 * ((t2 - t) / (t2 - t1)) + p2 * ((t - t1) / (t2 - t1));
+    let a3 = p2 * ((t3 - t) / (t3 - t2)) + p3 * ((t - t2) / (t3 - t2));
+    let b1 = a1 * ((t2 - t) / (t2 - t0)) + a2 * ((t - t0) / (t2 - t0));
+    let b2 = a2 * ((t3 - t) / (t3 - t1)) + a3 * ((t - t1) / (t3 - t1));
+    b1 * ((t2 - t) / (t2 - t1)) + b2 * ((t - t1) / (t2 - t1))
+}
+
+/// Which way on the ground a heading faces. A heading of 0 looks
+/// down -z like a camera, turning left is positive.
+pub fn forward(heading: f32) -> Vec2 {
+    Vec2::new(-heading.sin(), -heading.cos())
+}
+
+/// Left of someone facing `direction` on the ground.
+pub fn left_of(direction: Vec2) -> Vec2 {
+    Vec2::new(direction.y, -direction.x)
+}
+
+/// The heading that faces `direction` on the ground.
+pub fn heading_of(direction: Vec2) -> f32 {
+    (-direction.x).atan2(-direction.y)
+}
+
+/// The shortest turn from one heading to another, in -PI..PI.
+pub fn heading_difference(from: f32, to: f32) -> f32 {
+    (to - from + PI).rem_euclid(TAU) - PI
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WAYPOINTS: [Vec2; 4] =
+        [Vec2::new(0.0, -10.0), Vec2::new(8.0, -14.0), Vec2::new(9.0, -30.0), Vec2::new(-5.0, -34.0)];
+
+    fn park_path() -> RidePath {
+        RidePath::new(Vec2::ZERO, 0.0, &WAYPOINTS)
+    }
+
+    #[test]
+    fn goes_through_every_waypoint() {
+        let path = park_path();
+        for waypoint in WAYPOINTS {
+            let closest = (0..=1000)
+                .map(|i| path.position(path.length() * i as f32 / 1000.0).distance(waypoint))
+                .fold(f32::MAX, f32::min);
+            assert!(closest < 0.1, "missed {} by {}", waypoint, closest);
+        }
+    }
+
+    #[test]
+    fn equal_distances_are_equally_far_apart() {
+        let path = park_path();
+        let step = 0.25;
+        let mut distance = 0.0;
+        while distance + step <= path.length() {
+            let apart = path.position(distance).distance(path.position(distance + step));
+            // a chord through a bend is a little shorter than the way round
+            assert!(apart <= step + 1e-3 && apart > step * 0.97, "{} apart at {}", apart, distance);
+            distance += step;
+        }
+    }
+
+    #[test]
+    fn sets_off_towards_the_heading() {
+        let heading = 0.6;
+        let waypoint = forward(heading) * 5.0 + left_of(forward(heading)) * 3.0;
+        let path = RidePath::new(Vec2::ZERO, heading, &[waypoint]);
+        // the curve leaves the start turned more towards the heading than a
+        // straight line to the waypoint would be
+        let off_heading = path.direction(0.0).angle_between(forward(heading)).abs();
+        assert!(off_heading < waypoint.angle_between(forward(heading)).abs() * 0.75, "{} off", off_heading);
+        assert_eq!(path.position(0.0), Vec2::ZERO);
+    }
+
+    #[test]
+    fn stays_put_without_anywhere_to_go() {
+        let path = RidePath::new(Vec2::new(1.0, 2.0), 0.0, &[Vec2::new(1.0, 2.0)]);
+        assert_eq!(path.length(), 0.0);
+        assert_eq!(path.position(3.0), Vec2::new(1.0, 2.0));
+        assert_eq!(path.direction(3.0), Vec2::ZERO);
+    }
+
+    #[test]
+    fn past_the_end_is_the_end() {
+        let path = park_path();
+        assert!(path.position(path.length() + 10.0).distance(WAYPOINTS[3]) < 1e-3);
+    }
+
+    #[test]
+    fn headings_turn_the_short_way() {
+        for heading in [0.0, 1.0, -2.5, 3.0] {
+            assert!((heading_of(forward(heading)) - heading).abs() < 1e-5);
+        }
+        assert!((heading_difference(3.0, -3.0) - (TAU - 6.0)).abs() < 1e-5);
+        assert!((heading_difference(-3.0, 3.0) + (TAU - 6.0)).abs() < 1e-5);
+    }
+}
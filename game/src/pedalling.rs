@@ -0,0 +1,39 @@
+use crate::*;
+
+// the cadence that rides at the usual speed, a relaxed pace on a stationary bike
+pub const FULL_SPEED_RPM: f32 = 60.0;
+// pedalling harder only gets you so far
+pub const MAX_SPEED: f32 = PLAYER_SPEED * 2.0;
+// seconds for the speed to catch up with the pedals, a hall sensor
+// only ticks once a turn so the cadence comes in steps
+pub const SPEED_SMOOTHING: f32 = 0.8;
+
+/// How the pedals drive the bike. Only controllers with a cadence
+/// sensor send one, without it the ride goes at `PLAYER_SPEED`.
+#[derive(Resource, Debug, Clone, Copy, PartialEq, Reflect)]
+#[reflect(Resource)]
+pub struct Pedalling {
+    /// the cadence that rides at `PLAYER_SPEED`, wheel sensors turn a lot faster than pedals
+    pub full_speed_rpm: f32,
+}
+
+impl Default for Pedalling {
+    fn default() -> Self {
+        Self {
+            full_speed_rpm: FULL_SPEED_RPM,
+        }
+    }
+}
+
+impl Pedalling {
+    /// The speed the bike settles at for this cadence.
+    pub fn target_speed(&self, rpm: f32) -> f32 {
+        (PLAYER_SPEED * rpm / self.full_speed_rpm.max(1.0)).clamp(0.0, MAX_SPEED)
+    }
+
+    /// Moves `speed` towards the one for this cadence, `dt` seconds later.
+    pub fn follow(&self, speed: f32, rpm: f32, dt: f32) -> f32 {
+        let alpha = dt / (SPEED_SMOOTHING + dt);
+        speed + (self.target_speed(rpm) - speed) * alpha
+    }
+}
@@ -0,0 +1,344 @@
+use std::{
+    io::{Read, Write},
+    net::{IpAddr, TcpListener, TcpStream, UdpSocket},
+    sync::{mpsc, Mutex},
+    thread,
+    time::{Duration, Instant},
+};
+
+use bevy::render::{
+    render_resource::{Extent3d, TextureDimension, TextureFormat},
+    texture::ImageSampler,
+};
+use qrcode::QrCode;
+use tungstenite::Message;
+
+use crate::*;
+
+pub const DEFAULT_PHONE_PORT: u16 = 8080;
+// a phone that hasn't said anything for this long went to sleep or out of range
+pub const PHONE_TIMEOUT: Duration = Duration::from_secs(5);
+// pixels per qr module, and the blank modules around it scanners want
+const QR_SCALE: usize = 6;
+const QR_QUIET_ZONE: usize = 4;
+
+const PAGE: &str = include_str!("../assets/phone/index.html");
+
+/// How far the phone is tilted, in degrees with left positive,
+/// and how many are connected. The last one to send wins.
+#[derive(Resource, Debug, Default)]
+pub struct PhoneTilt {
+    pub degrees: f32,
+    pub phones: usize,
+}
+
+/// The address to open on the phone, and the same as a qr code
+/// once it's made at startup.
+#[derive(Resource, Debug, Clone)]
+pub struct PhoneLink {
+    pub url: String,
+    pub qr_code: Handle<Image>,
+}
+
+#[derive(Component)]
+pub struct PhoneLinkPanel;
+
+#[derive(Component)]
+pub struct PhoneLinkText;
+
+/// Serves a page that streams the phone's tilt back over a websocket,
+/// for demos without the handlebar. Only runs with `input = phone`,
+/// and falls back to the keyboard if the port is taken.
+pub struct PhonePlugin {
+    pub port: Option<u16>,
+}
+
+impl PhonePlugin {
+    pub fn new(config: &ControllerConfig) -> Self {
+        Self {
+            port: (config.backend == InputBackend::Phone).then_some(config.phone_port),
+        }
+    }
+}
+
+enum PhoneMessage {
+    Tilt(f32),
+    Connected,
+    Disconnected,
+}
+
+#[derive(Resource)]
+struct PhoneChannel(Mutex<mpsc::Receiver<PhoneMessage>>);
+
+impl Plugin for PhonePlugin {
+    fn build(&self, app: &mut App) {
+        let Some(port) = self.port else {
+            return;
+        };
+        let listener = match TcpListener::bind(("0.0.0.0", port)) {
+            Ok(listener) => listener,
+            Err(e) => {
+                error!("could not serve the phone page on port {}: {}, falling back to keyboard", port, e);
+                app.insert_resource(InputBackend::Keyboard);
+                return;
+            },
+        };
+
+        let (sender, receiver) = mpsc::channel();
+        thread::Builder::new()
+            .name("phone server".to_string())
+            .spawn(move || serve(listener, sender))
+            .expect("failed to spawn phone server thread");
+
+        let host = local_ip().unwrap_or_else(|| {
+            warn!("could not tell this computer's address, the phone needs to be told by hand");
+            IpAddr::from([127, 0, 0, 1])
+        });
+        let url = format!("http://{}:{}", host, port);
+        info!("steer with a phone at {}", url);
+
+        app
+        .insert_resource(PhoneChannel(Mutex::new(receiver)))
+        .init_resource::<PhoneTilt>()
+        .insert_resource(PhoneLink { url, qr_code: Handle::default() })
+        .add_systems(Startup, make_qr_code)
+        .add_systems(PreUpdate, receive_phone)
+        .add_systems(Update, read_phone
+            .in_set(InputSet)
+            .before(apply_samples)
+            .run_if(reading_input())
+            .run_if(resource_equals(InputBackend::Phone)))
+        .add_systems(OnEnter(GameState::MainMenu), spawn_phone_link)
+        .add_systems(Update, update_phone_link.run_if(in_state(GameState::MainMenu)))
+        .add_systems(OnExit(GameState::MainMenu), despawn_phone_link)
+        ;
+    }
+}
+
+fn make_qr_code(
+    mut link: ResMut<PhoneLink>,
+    mut images: ResMut<Assets<Image>>,
+) {
+    match QrCode::new(link.url.as_bytes()) {
+        Ok(code) => link.qr_code = images.add(qr_image(&code)),
+        Err(e) => warn!("could not make a qr code for {}: {}", link.url, e),
+    }
+}
+
+fn qr_image(code: &QrCode) -> Image {
+    let modules = code.width();
+    let size = (modules + 2 * QR_QUIET_ZONE) * QR_SCALE;
+    let colors = code.to_colors();
+    let mut data = Vec::with_capacity(size * size * 4);
+    for y in 0..size {
+        for x in 0..size {
+            let (mx, my) = ((x / QR_SCALE).wrapping_sub(QR_QUIET_ZONE), (y / QR_SCALE).wrapping_sub(QR_QUIET_ZONE));
+            let dark = mx < modules && my < modules && colors[my * modules + mx] == qrcode::Color::Dark;
+            let value = if dark { 0 } else { 255 };
+            data.extend_from_slice(&[value, value, value, 255]);
+        }
+    }
+
+    let mut image = Image::new(
+        Extent3d { width: size as u32, height: size as u32, depth_or_array_layers: 1 },
+        TextureDimension::D2,
+        data,
+        TextureFormat::Rgba8UnormSrgb,
+    );
+    // blurry edges between modules put scanners off
+    image.sampler_descriptor = ImageSampler::nearest();
+    image
+}
+
+// the address other devices on the network reach us at, connecting
+// a udp socket doesn't send anything but picks the outgoing interface
+fn local_ip() -> Option<IpAddr> {
+    let socket = UdpSocket::bind("0.0.0.0:0").ok()?;
+    socket.connect("192.0.2.1:80").ok()?;
+    socket.local_addr().ok().map(|addr| addr.ip())
+}
+
+fn receive_phone(
+    channel: Res<PhoneChannel>,
+    mut tilt: ResMut<PhoneTilt>,
+) {
+    let receiver = channel.0.lock().unwrap();
+    for message in receiver.try_iter() {
+        match message {
+            PhoneMessage::Tilt(degrees) => tilt.degrees = degrees,
+            PhoneMessage::Connected => {
+                info!("phone connected");
+                tilt.phones += 1;
+            },
+            PhoneMessage::Disconnected => {
+                info!("phone disconnected");
+                tilt.phones = tilt.phones.saturating_sub(1);
+                // don't keep steering with the last reading
+                if tilt.phones == 0 {
+                    tilt.degrees = 0.0;
+                }
+            },
+        }
+    }
+}
+
+fn read_phone(
+    tilt: Res<PhoneTilt>,
+    mut sample_events: EventWriter<ControllerSampleEvent>,
+) {
+    // tilted as far as the handlebar goes is a full tilt
+    let value = (tilt.degrees.to_radians() / FULL_TILT_ROLL).clamp(-1.0, 1.0);
+    sample_events.send(ControllerSampleEvent(PlayerId::FIRST, ControllerSample::from_tilt(value * FULL_TILT)));
+}
+
+fn spawn_phone_link(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    link: Res<PhoneLink>,
+) {
+    commands.spawn((
+        NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                right: Val::Percent(2.0),
+                bottom: Val::Percent(2.0),
+                flex_direction: FlexDirection::Column,
+                align_items: AlignItems::Center,
+                ..default()
+            },
+            // over the menu's background
+            z_index: ZIndex::Global(1),
+            ..default()
+        },
+        PhoneLinkPanel,
+        Name::new("Phone_Link"),
+    )).with_children(|commands| {
+        commands.spawn(ImageBundle {
+            style: Style {
+                width: Val::Px(180.0),
+                height: Val::Px(180.0),
+                ..default()
+            },
+            image: link.qr_code.clone().into(),
+            ..default()
+        });
+        commands.spawn((
+            TextBundle {
+                style: Style {
+                    margin: UiRect::top(Val::Px(8.0)),
+                    ..default()
+                },
+                text: Text::from_section(format!("Steer with your phone:\n{}", link.url), TextStyle {
+                    font: asset_server.load("fonts/Gorditas-Bold.ttf"),
+                    font_size: 24.0,
+                    color: Color::BLACK,
+                }),
+                ..default()
+            },
+            PhoneLinkText,
+        ));
+    });
+}
+
+fn update_phone_link(
+    tilt: Res<PhoneTilt>,
+    link: Res<PhoneLink>,
+    mut text_q: Query<&mut Text, With<PhoneLinkText>>,
+) {
+    if !tilt.is_changed() {
+        return;
+    }
+    for mut text in text_q.iter_mut() {
+        text.sections[0].value = match tilt.phones {
+            0 => format!("Steer with your phone:\n{}", link.url),
+            _ => format!("Phone connected, tilt it to steer\n{}", link.url),
+        };
+    }
+}
+
+fn despawn_phone_link(
+    mut commands: Commands,
+    panel_q: Query<Entity, With<PhoneLinkPanel>>,
+) {
+    for entity in panel_q.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+// runs until the game goes away and drops the receiver
+fn serve(listener: TcpListener, sender: mpsc::Sender<PhoneMessage>) {
+    for stream in listener.incoming() {
+        let Ok(stream) = stream else {
+            continue;
+        };
+        let sender = sender.clone();
+        // a phone per thread, there's never more than a handful
+        thread::spawn(move || {
+            if let Err(e) = handle_connection(stream, &sender) {
+                debug!("phone connection ended: {}", e);
+            }
+        });
+    }
+}
+
+fn handle_connection(mut stream: TcpStream, sender: &mpsc::Sender<PhoneMessage>) -> Result<(), String> {
+    stream.set_read_timeout(Some(PHONE_TIMEOUT)).map_err(|e| e.to_string())?;
+    let (head, head_len) = peek_request_head(&stream)?;
+
+    if !head.contains("upgrade: websocket") {
+        let mut request = vec![0; head_len];
+        stream.read_exact(&mut request).map_err(|e| e.to_string())?;
+        let (status, body) = match head.starts_with("get / ") || head.starts_with("get /index.html ") {
+            true => ("200 OK", PAGE),
+            false => ("404 Not Found", "not here"),
+        };
+        let response = format!(
+            "HTTP/1.1 {}\r\nContent-Type: text/html; charset=utf-8\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            status, body.len(), body,
+        );
+        return stream.write_all(response.as_bytes()).map_err(|e| e.to_string());
+    }
+
+    let mut socket = tungstenite::accept(stream).map_err(|e| e.to_string())?;
+    sender.send(PhoneMessage::Connected).map_err(|e| e.to_string())?;
+    let result = loop {
+        match socket.read() {
+            Ok(Message::Text(text)) => {
+                // anything that isn't a number of degrees is skipped,
+                // like the pings that keep a phone lying still from timing out
+                if let Some(degrees) = text.trim().parse::<f32>().ok().filter(|degrees| degrees.is_finite()) {
+                    if sender.send(PhoneMessage::Tilt(degrees)).is_err() {
+                        break Ok(());
+                    }
+                }
+            },
+            Ok(Message::Close(_)) => break Ok(()),
+            Ok(_) => {},
+            Err(e) => break Err(e.to_string()),
+        }
+    };
+    let _ = sender.send(PhoneMessage::Disconnected);
+    result
+}
+
+// looks at the request without taking it, the websocket handshake wants to read it itself,
+// gives back the head in lowercase and how many bytes it is
+fn peek_request_head(stream: &TcpStream) -> Result<(String, usize), String> {
+    let started = Instant::now();
+    let mut buffer = [0u8; 4096];
+    loop {
+        let n = stream.peek(&mut buffer).map_err(|e| e.to_string())?;
+        if n == 0 {
+            return Err("closed before sending a request".to_string());
+        }
+        if let Some(end) = buffer[..n].windows(4).position(|window| window == b"\r\n\r\n") {
+            let len = end + 4;
+            return Ok((String::from_utf8_lossy(&buffer[..len]).to_lowercase(), len));
+        }
+        if n == buffer.len() || started.elapsed() > PHONE_TIMEOUT {
+            return Err("request head too long or too slow".to_string());
+        }
+        // the rest of the head is still on the way
+        thread::sleep(Duration::from_millis(10));
+    }
+}
@@ -1,26 +1,57 @@
 use bevy::prelude::*;
 use bevy::math::Vec3Swizzles;
+use rand::SeedableRng;
 
 use crate::*;
 
 pub const PLAYER_SPEED: f32 = 0.8;
 pub const BALANCE_BASE: f32 = 0.0;
 pub const BALANCE_WIGGLE_ROOM: f32 = 10.0;
+pub const GRAVITY: f32 = 9.81;
+// seconds for the bike to turn to where the path goes
+pub const HEADING_SMOOTHING: f32 = 0.3;
+// seconds for the camera to settle into a lean
+pub const CAMERA_LEAN_SMOOTHING: f32 = 0.4;
+// radians, leaning further than this makes people queasy
+pub const MAX_CAMERA_LEAN: f32 = 0.2;
 
+/// Where the ride starts and the curve it follows, from the park.
 #[derive(Resource)]
 pub struct PlayerPath {
-    waypoints: Vec<Vec2>
+    pub start: Transform,
+    pub spline: RidePath,
+    /// metres across, the grass starts past half of it either side
+    pub width: f32,
 }
 
 #[derive(Component, Reflect, Default)]
 #[reflect(Component)]
 pub struct Player {
+    /// degrees the bike leans to the left
     pub balance: f32,
+    /// degrees per second it's tipping over at
+    pub lean_rate: f32,
+    /// seconds it's been leaning too far to ride
+    pub tipped_for: f32,
     pub speed: f32,
-    pub path_index: usize,
+    /// how far along the path the bike is
+    pub distance: f32,
+    /// radians turned to the left, lags behind the path a little
+    pub heading: f32,
+    /// metres left of the middle of the path
+    pub offset: f32,
+    /// metres slid sideways for each metre ridden, from leaning
+    pub drift: f32,
     pub disrupt_timer: Timer
 }
 
+/// The rider's view, it leans into the bends.
+#[derive(Component, Reflect, Default)]
+#[reflect(Component)]
+pub struct PlayerCamera {
+    pub lean: f32,
+}
+
 #[derive(Component, Reflect, Default)]
 #[reflect(Component)]
 pub struct Handlebar {
@@ -33,6 +64,11 @@ pub struct Indicator {
     pub prev_rotation: f32,
 }
 
+/// The systems riding the bike. They run after the input is read,
+/// and in a fixed order so a replayed ride comes out the same.
+#[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct PlayerSet;
+
 pub struct PlayerPlugin;
 
 impl Plugin for PlayerPlugin {
@@ -40,95 +76,114 @@ impl Plugin for PlayerPlugin {
         app
         .register_type::<Player>()
         .register_type::<Handlebar>()
-        .insert_resource(PlayerPath {
-            waypoints: vec![
-                Vec2::new(1.0, 1.0),
-                Vec2::new(2.0, 3.0),
-                Vec2::new(2.0, -2.0),
-                Vec2::new(5.0, -1.0),
-                Vec2::new(-2.0, -1.0),
-                Vec2::new(2.0, 0.0),
-                Vec2::new(6.0, 0.0),
-                Vec2::new(4.0, 3.0),
-                Vec2::new(0.0, 0.0),
-            ]
-        })
+        .register_type::<PlayerCamera>()
+        .register_type::<Steering>()
         .add_systems(OnEnter(GameState::Gameplay), spawn_player)
         .add_systems(OnExit(GameState::Gameplay), despawn_player)
-        .add_systems(Update, move_player.run_if(in_state(GameState::Gameplay)))
-        .add_systems(Update, disrupt_player.run_if(in_state(GameState::Gameplay)))
-        .add_systems(Update, controller_events.run_if(in_state(GameState::Gameplay)))
+        // nobody's riding while the bike lies in the grass
+        .configure_set(Update, PlayerSet.after(InputSet).run_if(riding))
+        .insert_resource(FixedTime::new_from_secs(LEAN_STEP))
+        .add_systems(OnEnter(GameState::Gameplay), reset_lean_clock)
+        .add_systems(FixedUpdate, lean_player
+            .run_if(in_state(GameState::Gameplay))
+            .run_if(controller_connected)
+            .run_if(riding))
+        .add_systems(Update, pedal_player
+            .in_set(PlayerSet)
+            .before(move_player)
+            .run_if(in_state(GameState::Gameplay))
+            .run_if(controller_connected))
+        .add_systems(Update, drift_player
+            .in_set(PlayerSet)
+            .before(move_player)
+            .run_if(in_state(GameState::Gameplay))
+            .run_if(controller_connected))
+        .add_systems(Update, move_player
+            .in_set(PlayerSet)
+            .before(controller_events)
+            .run_if(in_state(GameState::Gameplay))
+            .run_if(controller_connected))
+        .add_systems(Update, fall_off
+            .in_set(PlayerSet)
+            .after(move_player)
+            .run_if(in_state(GameState::Gameplay))
+            .run_if(resource_equals(LineMode::Lean))
+            .run_if(controller_connected))
+        .add_systems(Update, disrupt_player
+            .in_set(PlayerSet)
+            .before(controller_events)
+            .run_if(in_state(GameState::Gameplay))
+            .run_if(controller_connected))
+        .add_systems(Update, controller_events
+            .in_set(PlayerSet)
+            .run_if(in_state(GameState::Gameplay))
+            .run_if(controller_connected))
+        // a switch is input like any other, it's recorded and played back
+        .add_systems(Update, toggle_steering_mode
+            .in_set(InputSet)
+            .run_if(in_state(GameState::Gameplay))
+            .run_if(not(replaying)))
         ;
     }
 }
 
+// a bump in the path knocks the bike over a bit, it's up to the rider to catch it
 fn disrupt_player(
-    mut _commands: Commands,
     mut player_q: Query<&mut Player>,
-    mut handle_q: Query<(&mut Transform, &mut Handlebar)>,
     mut rng_q: Query<&mut EntropyComponent<ChaCha8Rng>>,
     time: Res<Time>,
     mut parrot_event_writer: EventWriter<DistressedParrotEvent>,
 ) {
     let mut player = player_q.single_mut();
-    let (mut handle_transform, mut handlebar) = handle_q.single_mut();
-        
+
     player.disrupt_timer.tick(time.delta());
     if player.disrupt_timer.just_finished() {
-        // all the transformation stuff could be separated
         let mut rng = rng_q.single_mut();
-        let rand_val = rng.gen_range(-45.0..45.0);
-        let angle = (rand_val * PI) / 180.0;
-        handle_transform.rotate_local_z(-handlebar.prev_rotation);
-        handle_transform.rotate_local_z(angle);
-        handlebar.prev_rotation = angle;
-
-        //let out_min = BALANCE_BASE - (BALANCE_BASE * 0.5);
-        //let out_max = BALANCE_BASE + (BALANCE_BASE * 0.5);
-        //player.balance = map_range(rand_val, -rand_range, rand_range, out_min, out_max);
-        player.balance = rand_val;
+        player.lean_rate += rng.gen_range(-DISRUPT_KICK..DISRUPT_KICK);
         parrot_event_writer.send(DistressedParrotEvent);
     }
 }
 
-fn map_range(num: f32, in_min: f32, in_max: f32, out_min: f32, out_max: f32) -> f32 {
-    return (num - in_min) * (out_max - out_min) / (in_max - in_min) + out_min;
+// time left over from the menus would otherwise add a step or two to the first
+// frames, a different number each time, and a replay would drift from the ride
+fn reset_lean_clock(mut fixed_time: ResMut<FixedTime>) {
+    *fixed_time = FixedTime::new_from_secs(LEAN_STEP);
 }
 
-fn clamp(num: f32, min: f32, max: f32) -> f32 {
-    let t = if num < min { min } else { num };
-    if t > max { max } else { t } 
+// runs at a fixed rate so the bike falls the same however fast the game draws
+fn lean_player(
+    mut player_q: Query<(&mut Player, &PlayerId)>,
+    movement_q: Query<(&Movement, &PlayerId)>,
+    steering: Res<Steering>,
+    fixed_time: Res<FixedTime>,
+) {
+    let (mut player, player_id) = player_q.single_mut();
+    let correction = movement_of(&movement_q, *player_id)
+        .map_or(0.0, |movement| steering.correction(movement));
+    let torque = correction * steering.rate * STEERING_TORQUE;
+
+    let lean = Lean { angle: player.balance, rate: player.lean_rate }
+        .step(player.speed, torque, fixed_time.period.as_secs_f32());
+    player.balance = lean.angle;
+    player.lean_rate = lean.rate;
 }
 
 fn controller_events(
-    mut player_q: Query<&mut Player>,
+    player_q: Query<(&Player, &PlayerId)>,
     mut handle_q: Query<(&mut Transform, &mut Handlebar), Without<Indicator>>,
     mut indicator_q: Query<(&mut Indicator, &mut Transform)>,
-    movement_q: Query<& Movement>,
-    time: Res<Time>,
+    movement_q: Query<(&Movement, &PlayerId)>,
     mut parrot_event_writer: EventWriter<RelaxedParrotEvent>
 ) {
-    let mut player = player_q.single_mut();
-    let movement = movement_q.single();
+    let (player, player_id) = player_q.single();
+    let Some(movement) = movement_of(&movement_q, *player_id) else {
+        return;
+    };
     //info!("b {}", player.balance);
 
     let (mut indicator, mut indicator_transform) = indicator_q.single_mut();
     let (mut handle_transform, mut handlebar) = handle_q.single_mut();
 
-    let step = 20.0 * time.delta_seconds();
-
-    match movement.direction {
-        Dir::Left => {
-            player.balance += step;
-        },
-        Dir::Right => {
-            player.balance -= step;
-        },
-        _ => {}
-    }
-
-    player.balance = clamp(player.balance, -45.0, 45.0);
-
     let new_angle = (player.balance * PI)/180.0;
     indicator_transform.rotate_local_z(-indicator.prev_rotation);
     indicator_transform.rotate_local_z(new_angle);
@@ -152,14 +207,25 @@ fn spawn_player(
     mut commands: Commands,
     game_assets: Res<GameAssets>,
     mut rng: ResMut<GlobalEntropy<ChaCha8Rng>>,
+    replay: Option<Res<Replay>>,
+    path: Res<PlayerPath>,
+    ride: Res<Ride>,
     mut sprite_params : Sprite3dParams,
 ) {
+    // a replayed ride has to roll the same dice as the recorded one
+    let seed = match replay {
+        Some(replay) => replay.seed,
+        None => rng.gen(),
+    };
+    commands.insert_resource(RideSeed(seed));
+
     let camera_player_id = commands.spawn((
         Camera3dBundle {
             transform: Transform::from_xyz(0.0, 0.0, 0.0)
                 .looking_at(Vec3::new(0.0, 0.0, 0.0), Vec3::Y),
             ..default()
         },
+        PlayerCamera::default(),
         Name::new("PlayerCam")
     )).id();
 
@@ -178,11 +244,11 @@ fn spawn_player(
     )).with_children(|commands| {
         // this selection could be randomized
         let colors = [ParrotType::Blue, ParrotType::Red, ParrotType::Blue,ParrotType::Red];
-        for i in 0..=3 {
+        for (i, color) in colors.iter().enumerate() {
             let x = -0.6 + (i as f32 * 0.40);
             let z = 0.01 + (i as f32 * 0.01);
             let xyz = Vec3::new(x, 0.5, z);
-            spawn_parrot(commands, &game_assets, &mut sprite_params, xyz, colors[i]);
+            spawn_parrot(commands, &game_assets, &mut sprite_params, xyz, *color);
         }
     })
     .id();
@@ -210,14 +276,21 @@ fn spawn_player(
         Name::new("Indicator"))).id();
 
     let mut player = commands.spawn((
-        SpatialBundle::from_transform(Transform::from_xyz(1.0, 0.5, 1.0)),
+        SpatialBundle::from_transform(path.start),
         Player {
             balance: BALANCE_BASE,
-            path_index: 0,
-            speed: PLAYER_SPEED,
-            disrupt_timer: Timer::from_seconds(5.0, TimerMode::Repeating),
+            lean_rate: 0.0,
+            tipped_for: 0.0,
+            distance: 0.0,
+            offset: 0.0,
+            drift: 0.0,
+            heading: path.start.rotation.to_euler(EulerRot::YXZ).0,
+            speed: ride.speed,
+            disrupt_timer: Timer::from_seconds(ride.disrupt_every, TimerMode::Repeating),
         },
-        EntropyComponent::from(&mut rng),
+        // one bike for now, ridden with the first controller
+        PlayerId::FIRST,
+        EntropyComponent::<ChaCha8Rng>::from_seed(seed),
         Name::new("Player")
     ));
     player.push_children(&[camera_player_id, handlebar_id, dash_id, indicator_id]);
@@ -231,35 +304,88 @@ fn despawn_player(
     commands.entity(entity).despawn_recursive();
 }
 
+fn pedal_player(
+    mut player_q: Query<(&mut Player, &PlayerId)>,
+    movement_q: Query<(&Movement, &PlayerId)>,
+    pedalling: Res<Pedalling>,
+    time: Res<Time>,
+) {
+    let (mut player, player_id) = player_q.single_mut();
+    // without a pedal sensor the bike rides itself like it always did
+    if let Some(rpm) = movement_of(&movement_q, *player_id).and_then(|movement| movement.cadence_rpm) {
+        player.speed = pedalling.follow(player.speed, rpm, time.delta_seconds());
+    }
+}
+
+// leaning one way slides the bike off that way, it only
+// comes back to the path by leaning the other way for a bit
+fn drift_player(
+    mut player_q: Query<&mut Player>,
+    line: Res<LineMode>,
+) {
+    let mut player = player_q.single_mut();
+    player.drift = match *line {
+        LineMode::Rails => 0.0,
+        LineMode::Lean => player.balance.to_radians().sin() * DRIFT_GAIN,
+    };
+}
+
 fn move_player(
-    mut _commands: Commands,
     path: Res<PlayerPath>,
     mut player_q: Query<(&mut Transform, &mut Player)>,
+    mut camera_q: Query<(&mut Transform, &mut PlayerCamera), Without<Player>>,
     mut game_state: ResMut<NextState<GameState>>,
     time: Res<Time>,
     parrots_q: Query<&Parrot>,
     mut game_over_event_writer: EventWriter<GameOverEvent>
 ) {
     let (mut transform, mut player) = player_q.single_mut();
+    let dt = time.delta_seconds();
 
-    let delta = player.speed * time.delta_seconds();
-
-    if path.waypoints.len() > player.path_index {
-        let delta_target = path.waypoints[player.path_index] - transform.translation.xz();
-
-        // this step will get us closer to the goal
-        if delta_target.length() > delta {
-            let movement = delta_target.normalize() * delta;
-            transform.translation += movement.extend(0.0).xzy();
-            // copy for ownership reasons
-            let y = transform.translation.y;
-            transform.look_at(path.waypoints[player.path_index].extend(y).xzy(), Vec3::Y);
-        } else {
-            player.path_index += 1;
-        }
-    } else {
+    // the same speed all the way, however the curve bends, slower on the grass
+    let on_grass = player.offset.abs() > path.width / 2.0;
+    let speed = if on_grass { player.speed * GRASS_SPEED } else { player.speed };
+    player.distance += speed * dt;
+    player.offset += player.drift * speed * dt;
+    if player.distance >= path.spline.length() {
         let parrots = parrots_q.iter().len();
-        game_over_event_writer.send(GameOverEvent(parrots));
+        game_over_event_writer.send(GameOverEvent(parrots, GameOverReason::Finished));
         game_state.set(GameState::GameOver);
+        return;
+    }
+
+    let direction = path.spline.direction(player.distance);
+    let ground = path.spline.position(player.distance) + left_of(direction) * player.offset;
+    transform.translation.x = ground.x;
+    transform.translation.z = ground.y;
+
+    // easing into turns instead of snapping, a sudden turn makes people sick
+    let target = heading_of(direction) + player.drift.atan();
+    let turn = heading_difference(player.heading, target) * dt / (HEADING_SMOOTHING + dt);
+    player.heading += turn;
+    transform.rotation = Quat::from_rotation_y(player.heading);
+
+    // leaning as much as a real bike would going round that bend
+    let (mut camera_transform, mut camera) = camera_q.single_mut();
+    let turn_rate = if dt > 0.0 { turn / dt } else { 0.0 };
+    let lean = (speed * turn_rate / GRAVITY).atan().clamp(-MAX_CAMERA_LEAN, MAX_CAMERA_LEAN);
+    camera.lean += (lean - camera.lean) * dt / (CAMERA_LEAN_SMOOTHING + dt);
+    camera_transform.rotation = Quat::from_rotation_z(camera.lean);
+}
+
+// too far into the long grass, or into the scenery, and the bike goes over
+fn fall_off(
+    path: Res<PlayerPath>,
+    player_q: Query<(&Transform, &Player)>,
+    obstacle_q: Query<(&Transform, &Obstacle), Without<Player>>,
+    mut crash_events: EventWriter<CrashEvent>,
+) {
+    let (transform, player) = player_q.single();
+    let in_long_grass = player.offset.abs() > path.width / 2.0 + GRASS_FALL;
+    let crashed = obstacle_q.iter().any(|(obstacle_transform, obstacle)| {
+        obstacle_transform.translation.xz().distance(transform.translation.xz()) < obstacle.radius + BIKE_RADIUS
+    });
+    if in_long_grass || crashed {
+        crash_events.send(CrashEvent(GameOverReason::OffPath));
     }
-}
\ No newline at end of file
+}
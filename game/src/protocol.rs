@@ -0,0 +1,119 @@
+use controller_protocol as wire;
+
+use crate::*;
+
+// The frame format lives in the controller-protocol crate, shared with the
+// firmware. This is the game's side of it, with bevy types.
+pub use wire::{
+    checksum,
+    Command as ControllerCommand,
+    BUTTON_BELL,
+    Error as ProtocolError,
+    CHECKSUM_START,
+    FRAME_START,
+    PROTOCOL_VERSION,
+};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ControllerHello {
+    pub version: u32,
+    pub device: String,
+    /// samples per second, if the controller says
+    pub rate_hz: Option<u32>,
+}
+
+/// Everything the controller reports in one reading. The legacy
+/// format only fills in `gyro.y`.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct ControllerSample {
+    pub seq: Option<u32>,
+    pub timestamp_ms: Option<u32>,
+    /// angular velocity in rad/s
+    pub gyro: Vec3,
+    /// acceleration in m/s^2, if the controller sends it
+    pub accel: Option<Vec3>,
+    /// bitmask of pressed buttons
+    pub buttons: u8,
+    /// roll in radians, if the controller estimates it itself
+    pub roll: Option<f32>,
+    /// pedal or wheel revolutions per minute, if there's a sensor for it
+    pub cadence_rpm: Option<f32>,
+}
+
+impl ControllerSample {
+    /// A sample for inputs that only know how far they're tilted,
+    /// put where the handlebar reports it.
+    pub fn from_tilt(tilt: f32) -> Self {
+        Self {
+            gyro: Vec3::new(0.0, tilt, 0.0),
+            ..default()
+        }
+    }
+}
+
+impl From<wire::Sample> for ControllerSample {
+    fn from(sample: wire::Sample) -> Self {
+        Self {
+            seq: sample.seq,
+            timestamp_ms: sample.timestamp_ms,
+            gyro: Vec3::from_array(sample.gyro),
+            accel: sample.accel.map(Vec3::from_array),
+            buttons: sample.buttons,
+            roll: sample.roll,
+            cadence_rpm: sample.cadence_rpm,
+        }
+    }
+}
+
+impl From<&ControllerSample> for wire::Sample {
+    fn from(sample: &ControllerSample) -> Self {
+        Self {
+            seq: sample.seq,
+            timestamp_ms: sample.timestamp_ms,
+            gyro: sample.gyro.to_array(),
+            accel: sample.accel.map(|accel| accel.to_array()),
+            buttons: sample.buttons,
+            roll: sample.roll,
+            cadence_rpm: sample.cadence_rpm,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Frame {
+    Hello(ControllerHello),
+    Sample(ControllerSample),
+    /// only the game sends these, a controller echoing one back is confused
+    Command(ControllerCommand),
+}
+
+/// Parses one trimmed, non-empty line in either the framed or the legacy format.
+pub fn parse_frame(line: &str) -> Result<Frame, ProtocolError<'_>> {
+    Ok(match wire::parse_frame(line)? {
+        wire::Frame::Hello(hello) => Frame::Hello(ControllerHello {
+            version: hello.version,
+            device: hello.device.to_string(),
+            rate_hz: hello.rate_hz,
+        }),
+        wire::Frame::Sample(sample) => Frame::Sample(sample.into()),
+        wire::Frame::Command(command) => Frame::Command(command),
+    })
+}
+
+/// Writes a sample as a frame, without the newline. Floats are written
+/// in full so parsing it again gives back the exact same sample.
+pub fn encode_sample(sample: &ControllerSample) -> String {
+    encode(wire::Frame::Sample(sample.into()))
+}
+
+/// Writes a command as a frame, with the newline the controller waits for.
+pub fn encode_command(command: &ControllerCommand) -> String {
+    encode(wire::Frame::Command(*command)) + "\n"
+}
+
+fn encode(frame: wire::Frame) -> String {
+    let mut line = String::new();
+    // writing to a String can't fail
+    let _ = frame.write(&mut line);
+    line
+}
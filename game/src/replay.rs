@@ -0,0 +1,532 @@
+use std::{
+    fs::{self, File},
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use bevy::time::TimeUpdateStrategy;
+
+use crate::*;
+
+// A recording is a `key = value` header with everything that shapes the ride,
+// then one `frame` line per game frame with its length in nanoseconds,
+// each followed by the samples that arrived in that frame:
+//
+//   seed = 5f1c...
+//   sensitivity = 1
+//   ---
+//   frame 16651083
+//   sample 0.016651 @S seq=12 gx=0 gy=-0.21 gz=0*1B
+//   steering Proportional
+//   ...
+//   end 12.5 -3.1 1.8 40.2
+//
+// Playing it back feeds the same samples in the same frames, with the same
+// frame lengths and rng seed, so the ride comes out exactly the same.
+// A `steering` line is the rider switching modes with Tab in that frame.
+// The `end` line is the lean and position the ride finished with, to check that it did.
+pub const RECORDING_VERSION: u32 = 2;
+pub const HEADER_END: &str = "---";
+pub const RECORDING_EXTENSION: &str = "ride";
+// the same ride played back on another machine can round a little differently
+pub const REPLAY_TOLERANCE: f32 = 0.001;
+
+/// The seed the player's rng started from this ride.
+#[derive(Resource, Debug, Clone, Copy)]
+pub struct RideSeed(pub [u8; 32]);
+
+/// The lean and position a ride finished with.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RideEnd {
+    pub balance: f32,
+    pub position: Vec3,
+}
+
+impl RideEnd {
+    /// Whether a ride finishing leaning `balance` at `position` ended here,
+    /// give or take `REPLAY_TOLERANCE`.
+    pub fn matches(&self, balance: f32, position: Vec3) -> bool {
+        (balance - self.balance).abs() <= REPLAY_TOLERANCE && position.abs_diff_eq(self.position, REPLAY_TOLERANCE)
+    }
+}
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct RecordedFrame {
+    pub delta: Duration,
+    pub samples: Vec<ControllerSample>,
+    /// the steering mode the rider switched to
+    pub steering: Option<SteeringMode>,
+}
+
+/// A recorded ride to play back instead of reading input.
+#[derive(Resource, Debug, Clone)]
+pub struct Replay {
+    pub seed: [u8; 32],
+    pub profile: CalibrationProfile,
+    pub steering: Steering,
+    pub pedalling: Pedalling,
+    pub line: LineMode,
+    pub frames: Vec<RecordedFrame>,
+    /// missing if the game was closed before the ride was over
+    pub end: Option<RideEnd>,
+    next_frame: usize,
+}
+
+#[derive(Resource)]
+struct RecordingDirectory(PathBuf);
+
+#[derive(Resource)]
+struct Recorder {
+    path: PathBuf,
+    writer: BufWriter<File>,
+    header_written: bool,
+    ride_time: Duration,
+    // the mode at the start of the ride, then the last one written down
+    steering: SteeringMode,
+}
+
+pub struct ReplayPlugin {
+    /// directory to write a recording of every ride into
+    pub record: Option<String>,
+    /// recording to play back
+    pub replay: Option<String>,
+}
+
+impl ReplayPlugin {
+    pub fn new(config: &GameConfig) -> Self {
+        Self {
+            record: config.record.clone(),
+            replay: config.replay.clone(),
+        }
+    }
+}
+
+impl Plugin for ReplayPlugin {
+    fn build(&self, app: &mut App) {
+        if let Some(path) = &self.replay {
+            match Replay::load(Path::new(path)) {
+                Ok(replay) => {
+                    info!("replaying {} with {} frames", path, replay.frames.len());
+                    app
+                    .insert_resource(replay)
+                    .add_systems(Startup, start_replay)
+                    .add_systems(Update, play_replay
+                        .in_set(InputSet)
+                        .before(apply_samples)
+                        .run_if(in_state(GameState::Gameplay))
+                        .run_if(resource_equals(InputBackend::Replay)))
+                    .add_systems(OnExit(GameState::Gameplay), (check_replay, rewind_replay).chain());
+                },
+                Err(e) => {
+                    error!("could not load recording {}: {}, falling back to keyboard", path, e);
+                    app.insert_resource(InputBackend::Keyboard);
+                },
+            }
+        }
+
+        if let Some(directory) = &self.record {
+            app
+            .insert_resource(RecordingDirectory(PathBuf::from(directory)))
+            .add_systems(OnEnter(GameState::Gameplay), start_recording)
+            .add_systems(Update, write_recording_header
+                .after(InputSet)
+                .before(record_frame)
+                .run_if(in_state(GameState::Gameplay))
+                .run_if(resource_exists::<Recorder>()))
+            .add_systems(Update, record_frame
+                .after(InputSet)
+                .run_if(in_state(GameState::Gameplay))
+                .run_if(resource_exists::<Recorder>()))
+            .add_systems(OnExit(GameState::Gameplay), stop_recording);
+        }
+    }
+}
+
+impl Replay {
+    pub fn load(path: &Path) -> Result<Self, String> {
+        Self::parse(&fs::read_to_string(path).map_err(|e| e.to_string())?)
+    }
+
+    pub fn parse(contents: &str) -> Result<Self, String> {
+        let (header, body) = contents
+            .split_once(&format!("\n{}\n", HEADER_END))
+            .ok_or("no end of header")?;
+
+        let mut seed = None;
+        let mut profile = CalibrationProfile::default();
+        // steering and pedalling are written with the same keys as in controller.cfg, the line as in game.cfg
+        let mut config = ControllerConfig::default();
+        let mut game = GameConfig::default();
+        for (key, value) in config_entries(header) {
+            match key {
+                "version" if value != RECORDING_VERSION.to_string() => {
+                    return Err(format!("unsupported version {}", value));
+                },
+                "version" => {},
+                "seed" => seed = Some(decode_seed(value).ok_or("bad seed")?),
+                "offset" => profile.offset = value.parse().map_err(|_| "bad offset")?,
+                "deadzone" => profile.deadzone = value.parse().map_err(|_| "bad deadzone")?,
+                "sensitivity" => profile.sensitivity = value.parse().map_err(|_| "bad sensitivity")?,
+                "line" => game.set(key, value),
+                _ => config.set(key, value),
+            }
+        }
+
+        let mut frames: Vec<RecordedFrame> = vec![];
+        let mut end = None;
+        for line in body.lines().filter(|line| !line.trim().is_empty()) {
+            let (kind, rest) = line.split_once(' ').ok_or_else(|| format!("bad line {:?}", line))?;
+            match kind {
+                "frame" => frames.push(RecordedFrame {
+                    delta: Duration::from_nanos(rest.parse().map_err(|_| format!("bad frame {:?}", line))?),
+                    samples: vec![],
+                    steering: None,
+                }),
+                "steering" => {
+                    let mode = rest.parse().map_err(|_| format!("bad steering {:?}", line))?;
+                    frames.last_mut().ok_or("steering before the first frame")?.steering = Some(mode);
+                },
+                "sample" => {
+                    let (_ride_time, frame) = rest.split_once(' ').ok_or_else(|| format!("bad sample {:?}", line))?;
+                    let Ok(Frame::Sample(sample)) = parse_frame(frame) else {
+                        return Err(format!("bad sample {:?}", line));
+                    };
+                    frames.last_mut().ok_or("sample before the first frame")?.samples.push(sample);
+                },
+                "end" => {
+                    let numbers = rest.split(' ')
+                        .map(|number| number.parse::<f32>())
+                        .collect::<Result<Vec<_>, _>>()
+                        .map_err(|_| format!("bad end {:?}", line))?;
+                    let [balance, x, y, z] = numbers[..] else {
+                        return Err(format!("bad end {:?}", line));
+                    };
+                    end = Some(RideEnd { balance, position: Vec3::new(x, y, z) });
+                },
+                _ => return Err(format!("bad line {:?}", line)),
+            }
+        }
+
+        Ok(Self {
+            seed: seed.ok_or("no seed")?,
+            profile,
+            steering: config.steering,
+            pedalling: config.pedalling,
+            line: game.line,
+            frames,
+            end,
+            next_frame: 0,
+        })
+    }
+
+    fn first_delta(&self) -> TimeUpdateStrategy {
+        match self.frames.first() {
+            Some(frame) => TimeUpdateStrategy::ManualDuration(frame.delta),
+            None => TimeUpdateStrategy::Automatic,
+        }
+    }
+}
+
+fn encode_seed(seed: &[u8; 32]) -> String {
+    seed.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn decode_seed(hex: &str) -> Option<[u8; 32]> {
+    let mut seed = [0u8; 32];
+    if hex.len() != seed.len() * 2 {
+        return None;
+    }
+    for (i, byte) in seed.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(hex.get(i * 2..i * 2 + 2)?, 16).ok()?;
+    }
+    Some(seed)
+}
+
+// the frame length is picked when the frame starts, before the ride does,
+// so the menus already run at the length of the first recorded frame
+fn start_replay(
+    mut commands: Commands,
+    replay: Res<Replay>,
+) {
+    commands.insert_resource(replay.profile.clone());
+    commands.insert_resource(replay.steering);
+    commands.insert_resource(replay.pedalling);
+    commands.insert_resource(replay.line);
+    commands.insert_resource(replay.first_delta());
+}
+
+/// Whether a recorded ride is playing instead of the rider.
+pub fn replaying(backend: Res<InputBackend>) -> bool {
+    *backend == InputBackend::Replay
+}
+
+fn play_replay(
+    mut replay: ResMut<Replay>,
+    mut time_strategy: ResMut<TimeUpdateStrategy>,
+    mut steering: ResMut<Steering>,
+    mut sample_events: EventWriter<ControllerSampleEvent>,
+) {
+    let Some(frame) = replay.frames.get(replay.next_frame) else {
+        return;
+    };
+    for sample in frame.samples.iter() {
+        sample_events.send(ControllerSampleEvent(PlayerId::FIRST, sample.clone()));
+    }
+    if let Some(mode) = frame.steering {
+        steering.mode = mode;
+    }
+
+    replay.next_frame += 1;
+    *time_strategy = match replay.frames.get(replay.next_frame) {
+        Some(next) => TimeUpdateStrategy::ManualDuration(next.delta),
+        None => {
+            info!("replay finished");
+            TimeUpdateStrategy::Automatic
+        },
+    };
+}
+
+// a replay that doesn't end where the ride did has drifted somewhere
+fn check_replay(
+    replay: Res<Replay>,
+    player_q: Query<(&Player, &Transform)>,
+) {
+    let (Some(end), Ok((player, transform))) = (replay.end, player_q.get_single()) else {
+        return;
+    };
+    if end.matches(player.balance, transform.translation) {
+        info!("replay ended where the ride did");
+    } else {
+        warn!(
+            "replay drifted, it ended leaning {} at {} but the ride leaned {} at {}",
+            player.balance, transform.translation, end.balance, end.position,
+        );
+    }
+}
+
+fn rewind_replay(
+    mut replay: ResMut<Replay>,
+    mut time_strategy: ResMut<TimeUpdateStrategy>,
+    mut steering: ResMut<Steering>,
+) {
+    replay.next_frame = 0;
+    *time_strategy = replay.first_delta();
+    *steering = replay.steering;
+}
+
+fn start_recording(
+    mut commands: Commands,
+    directory: Res<RecordingDirectory>,
+    steering: Res<Steering>,
+) {
+    let started = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+    let path = directory.0.join(format!("ride-{}.{}", started.as_secs(), RECORDING_EXTENSION));
+
+    let file = fs::create_dir_all(&directory.0).and_then(|_| File::create(&path));
+    match file {
+        Ok(file) => commands.insert_resource(Recorder {
+            path,
+            writer: BufWriter::new(file),
+            header_written: false,
+            ride_time: Duration::ZERO,
+            steering: steering.mode,
+        }),
+        Err(e) => error!("could not record ride to {}: {}", path.display(), e),
+    }
+}
+
+// waits for the first frame, the seed is only picked when the player spawns
+fn write_recording_header(
+    mut recorder: ResMut<Recorder>,
+    seed: Res<RideSeed>,
+    backend: Res<InputBackend>,
+    calibration: Res<CalibrationProfile>,
+    steering: Res<Steering>,
+    pedalling: Res<Pedalling>,
+    line: Res<LineMode>,
+) {
+    if recorder.header_written {
+        return;
+    }
+
+    let steering = Steering { mode: recorder.steering, ..*steering };
+    let header = recording_header(&seed.0, &backend.profile(&calibration), &steering, &pedalling, *line);
+    if let Err(e) = recorder.writer.write_all(header.as_bytes()) {
+        error!("could not record to {}: {}", recorder.path.display(), e);
+    }
+    recorder.header_written = true;
+}
+
+fn record_frame(
+    mut commands: Commands,
+    mut recorder: ResMut<Recorder>,
+    mut sample_events: EventReader<ControllerSampleEvent>,
+    steering: Res<Steering>,
+    time: Res<Time>,
+) {
+    let recorder = recorder.as_mut();
+
+    recorder.ride_time += time.delta();
+    // the ride is the first player's
+    let samples: Vec<_> = sample_events.iter()
+        .filter(|event| event.0 == PlayerId::FIRST)
+        .map(|event| &event.1)
+        .collect();
+    let switched = (steering.mode != recorder.steering).then_some(steering.mode);
+    if let Some(mode) = switched {
+        recorder.steering = mode;
+    }
+    let lines = frame_lines(time.delta(), recorder.ride_time, &samples, switched);
+
+    if let Err(e) = recorder.writer.write_all(lines.as_bytes()) {
+        error!("stopped recording to {}: {}", recorder.path.display(), e);
+        commands.remove_resource::<Recorder>();
+    }
+}
+
+fn stop_recording(
+    mut commands: Commands,
+    recorder: Option<ResMut<Recorder>>,
+    player_q: Query<(&Player, &Transform)>,
+) {
+    let Some(mut recorder) = recorder else {
+        return;
+    };
+    if let Ok((player, transform)) = player_q.get_single() {
+        let end = end_line(&RideEnd { balance: player.balance, position: transform.translation });
+        if let Err(e) = recorder.writer.write_all(end.as_bytes()) {
+            error!("could not finish recording {}: {}", recorder.path.display(), e);
+        }
+    }
+    match recorder.writer.flush() {
+        Ok(_) => info!("recorded ride to {}", recorder.path.display()),
+        Err(e) => error!("could not finish recording {}: {}", recorder.path.display(), e),
+    }
+    commands.remove_resource::<Recorder>();
+}
+
+// everything `Replay::parse` reads back before the frames
+fn recording_header(
+    seed: &[u8; 32],
+    profile: &CalibrationProfile,
+    steering: &Steering,
+    pedalling: &Pedalling,
+    line: LineMode,
+) -> String {
+    format!(
+        "# a ride recorded by ridingintheparkwithparrots, play it back with --replay\n\
+        version = {}\nseed = {}\n\
+        offset = {}\ndeadzone = {}\nsensitivity = {}\n\
+        steering = {:?}\nresponse = {:?}\nsteering_rate = {}\nresponse_exponent = {}\nresponse_deadzone = {}\n\
+        full_speed_rpm = {}\n\
+        line = {:?}\n\
+        {}\n",
+        RECORDING_VERSION, encode_seed(seed),
+        profile.offset, profile.deadzone, profile.sensitivity,
+        steering.mode, steering.curve, steering.rate, steering.exponent, steering.deadzone,
+        pedalling.full_speed_rpm,
+        line,
+        HEADER_END,
+    )
+}
+
+fn frame_lines(
+    delta: Duration,
+    ride_time: Duration,
+    samples: &[&ControllerSample],
+    steering: Option<SteeringMode>,
+) -> String {
+    let mut lines = format!("frame {}\n", delta.as_nanos());
+    for sample in samples {
+        lines += &format!("sample {:.6} {}\n", ride_time.as_secs_f64(), encode_sample(sample));
+    }
+    if let Some(mode) = steering {
+        lines += &format!("steering {:?}\n", mode);
+    }
+    lines
+}
+
+fn end_line(end: &RideEnd) -> String {
+    format!("end {} {} {} {}\n", end.balance, end.position.x, end.position.y, end.position.z)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample(seq: u32, gy: f32) -> ControllerSample {
+        ControllerSample {
+            seq: Some(seq),
+            gyro: Vec3::new(0.0, gy, 0.0),
+            ..default()
+        }
+    }
+
+    #[test]
+    fn documented_frame_is_what_gets_recorded() {
+        // the example at the top of this file
+        let documented = "frame 16651083\nsample 0.016651 @S seq=12 gx=0 gy=-0.21 gz=0*1B\n";
+        let delta = Duration::from_nanos(16651083);
+        assert_eq!(frame_lines(delta, delta, &[&sample(12, -0.21)], None), documented);
+    }
+
+    #[test]
+    fn recordings_read_back_the_same() {
+        let seed = [7u8; 32];
+        let profile = CalibrationProfile { offset: 0.05, deadzone: 0.12, sensitivity: -1.5 };
+        let steering = Steering { mode: SteeringMode::Proportional, curve: ResponseCurve::Exponential, ..default() };
+        let pedalling = Pedalling { full_speed_rpm: 90.0 };
+        let end = RideEnd { balance: -12.25, position: Vec3::new(1.5, 0.15, -40.125) };
+
+        let first = Duration::from_millis(16);
+        let second = Duration::from_millis(17);
+        let recording = recording_header(&seed, &profile, &steering, &pedalling, LineMode::Lean)
+            + &frame_lines(first, first, &[&sample(1, 0.5), &sample(2, -0.25)], None)
+            + &frame_lines(second, first + second, &[], Some(SteeringMode::Digital))
+            + &end_line(&end);
+
+        let replay = Replay::parse(&recording).unwrap();
+        assert_eq!(replay.seed, seed);
+        assert_eq!(replay.profile, profile);
+        assert_eq!(replay.steering, steering);
+        assert_eq!(replay.pedalling, pedalling);
+        assert_eq!(replay.line, LineMode::Lean);
+        assert_eq!(replay.frames, vec![
+            RecordedFrame { delta: first, samples: vec![sample(1, 0.5), sample(2, -0.25)], steering: None },
+            RecordedFrame { delta: second, samples: vec![], steering: Some(SteeringMode::Digital) },
+        ]);
+        assert_eq!(replay.end, Some(end));
+    }
+
+    #[test]
+    fn unfinished_rides_have_no_end() {
+        let recording = recording_header(&[0; 32], &default(), &default(), &Pedalling::default(), default())
+            + &frame_lines(Duration::from_millis(16), Duration::from_millis(16), &[], None);
+        assert_eq!(Replay::parse(&recording).unwrap().end, None);
+    }
+
+    #[test]
+    fn other_versions_are_refused() {
+        let recording = recording_header(&[0; 32], &default(), &default(), &Pedalling::default(), default())
+            .replace(&format!("version = {}", RECORDING_VERSION), "version = 1");
+        assert_eq!(Replay::parse(&recording).unwrap_err(), "unsupported version 1");
+    }
+
+    #[test]
+    fn broken_lines_are_errors() {
+        let header = recording_header(&[0; 32], &default(), &default(), &Pedalling::default(), default());
+        assert!(Replay::parse(&(header.clone() + "sample 0.1 @S seq=1 gx=0 gy=0 gz=0*00\n")).is_err());
+        assert!(Replay::parse(&(header.clone() + "frame 16000000\nend 1 2 3\n")).is_err());
+        assert!(Replay::parse(&(header + "frame 16000000\nsteering sideways\n")).is_err());
+    }
+
+    #[test]
+    fn rides_end_where_they_did_give_or_take_rounding() {
+        let end = RideEnd { balance: 3.5, position: Vec3::new(1.0, 0.15, -20.0) };
+        assert!(end.matches(3.5, Vec3::new(1.0, 0.15, -20.0)));
+        assert!(end.matches(3.5 + REPLAY_TOLERANCE * 0.5, Vec3::new(1.0, 0.15, -20.0 - REPLAY_TOLERANCE * 0.5)));
+        assert!(!end.matches(3.6, Vec3::new(1.0, 0.15, -20.0)));
+        assert!(!end.matches(3.5, Vec3::new(1.0, 0.15, -20.1)));
+    }
+}
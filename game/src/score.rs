@@ -18,7 +18,6 @@ impl Plugin for ScorePlugin {
 }
 
 fn add_score(
-    mut commands: Commands,
     mut game_over_event_reader: EventReader<GameOverEvent>,
     mut score_q: Query<&mut Score>
 ) {
@@ -0,0 +1,223 @@
+use std::{
+    io::{ErrorKind, Read, Write},
+    sync::{mpsc, Mutex},
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::*;
+
+pub const READ_TIMEOUT: Duration = Duration::from_millis(100);
+pub const RECONNECT_INTERVAL: Duration = Duration::from_secs(1);
+// the controllers send several readings a second, so this long without one means trouble
+pub const SILENCE_TIMEOUT: Duration = Duration::from_secs(2);
+// a board plugged in as the game starts takes a moment to show up
+pub const STARTUP_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// Bytes read from the port with the given label.
+#[derive(Event)]
+pub struct SerialReadEvent(pub String, pub Vec<u8>);
+
+/// Bytes to write to the port with the given label. Dropped while it's disconnected.
+#[derive(Event)]
+pub struct SerialWriteEvent(pub String, pub Vec<u8>);
+
+#[derive(Event, Debug, Clone, PartialEq)]
+pub enum SerialConnectionEvent {
+    Connected { label: String, port_name: String },
+    Disconnected { label: String },
+    /// It wasn't there at startup and isn't waited for.
+    Unavailable { label: String },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SerialSetting {
+    pub label: String,
+    /// `None` to look for it, with `auto_detect`
+    pub port_name: Option<String>,
+    pub baud_rate: u32,
+    /// look for the controller if there's no `port_name` or it disappears,
+    /// usb serial devices often come back under a new name
+    pub auto_detect: bool,
+    /// ports other controllers are on, never picked when looking for this one
+    pub taken: Vec<String>,
+    /// give up if it can't be found or opened within `STARTUP_TIMEOUT`,
+    /// once it's been there it's always waited for
+    pub fallback: bool,
+}
+
+/// Reads and writes serial ports on background threads, so a port that goes away
+/// doesn't block the game. Lost ports are reopened as soon as they're back.
+pub struct SerialPlugin {
+    pub settings: Vec<SerialSetting>,
+}
+
+enum SerialMessage {
+    Data(Vec<u8>),
+    Connected(String),
+    Disconnected,
+    Unavailable,
+}
+
+struct SerialChannel {
+    label: String,
+    receiver: Mutex<mpsc::Receiver<SerialMessage>>,
+    sender: Mutex<mpsc::Sender<Vec<u8>>>,
+}
+
+#[derive(Resource)]
+struct SerialChannels(Vec<SerialChannel>);
+
+impl Plugin for SerialPlugin {
+    fn build(&self, app: &mut App) {
+        let channels = self.settings.iter()
+            .map(|setting| {
+                let (sender, receiver) = mpsc::channel();
+                let (write_sender, write_receiver) = mpsc::channel();
+                let label = setting.label.clone();
+                let setting = setting.clone();
+                thread::Builder::new()
+                    .name(format!("serial {}", label))
+                    .spawn(move || run_port(setting, sender, write_receiver))
+                    .expect("failed to spawn serial thread");
+                SerialChannel {
+                    label,
+                    receiver: Mutex::new(receiver),
+                    sender: Mutex::new(write_sender),
+                }
+            })
+            .collect();
+
+        app
+        .insert_resource(SerialChannels(channels))
+        .add_event::<SerialReadEvent>()
+        .add_event::<SerialWriteEvent>()
+        .add_event::<SerialConnectionEvent>()
+        .add_systems(PreUpdate, receive_serial)
+        .add_systems(PostUpdate, send_serial)
+        ;
+    }
+}
+
+fn receive_serial(
+    channels: Res<SerialChannels>,
+    mut read_events: EventWriter<SerialReadEvent>,
+    mut connection_events: EventWriter<SerialConnectionEvent>,
+) {
+    for port in channels.0.iter() {
+        let receiver = port.receiver.lock().unwrap();
+        for message in receiver.try_iter() {
+            match message {
+                SerialMessage::Data(bytes) => read_events.send(SerialReadEvent(port.label.clone(), bytes)),
+                SerialMessage::Connected(port_name) => {
+                    info!("serial {} connected on {}", port.label, port_name);
+                    connection_events.send(SerialConnectionEvent::Connected { label: port.label.clone(), port_name });
+                },
+                SerialMessage::Disconnected => {
+                    warn!("serial {} disconnected", port.label);
+                    connection_events.send(SerialConnectionEvent::Disconnected { label: port.label.clone() });
+                },
+                SerialMessage::Unavailable => {
+                    warn!("serial {} not found, giving up on it", port.label);
+                    connection_events.send(SerialConnectionEvent::Unavailable { label: port.label.clone() });
+                },
+            }
+        }
+    }
+}
+
+fn send_serial(
+    channels: Res<SerialChannels>,
+    mut write_events: EventReader<SerialWriteEvent>,
+) {
+    for SerialWriteEvent(label, bytes) in write_events.iter() {
+        let Some(port) = channels.0.iter().find(|port| &port.label == label) else {
+            warn!("no serial port {} to write to", label);
+            continue;
+        };
+        // only fails once the thread is gone, and then nothing's listening anyway
+        let _ = port.sender.lock().unwrap().send(bytes.clone());
+    }
+}
+
+// runs until the game goes away and drops the receiver
+fn run_port(
+    mut setting: SerialSetting,
+    sender: mpsc::Sender<SerialMessage>,
+    writes: mpsc::Receiver<Vec<u8>>,
+) {
+    let mut buffer = [0u8; 1024];
+    // it's tried every second, saying so once until it works is plenty
+    let mut warned = false;
+    let started = Instant::now();
+
+    loop {
+        if setting.port_name.is_none() && setting.auto_detect {
+            setting.port_name = find_controller_port(setting.baud_rate, &setting.taken);
+        }
+        let opened = match &setting.port_name {
+            Some(port_name) => serialport::new(port_name, setting.baud_rate)
+                .timeout(READ_TIMEOUT)
+                .open()
+                .map(|port| (port, port_name.clone()))
+                .map_err(|e| format!("could not open {}: {}", port_name, e)),
+            None => Err("no controller found".to_string()),
+        };
+        let (mut port, port_name) = match opened {
+            Ok(opened) => opened,
+            Err(e) => {
+                if setting.fallback && started.elapsed() > STARTUP_TIMEOUT {
+                    let _ = sender.send(SerialMessage::Unavailable);
+                    return;
+                }
+                if !warned {
+                    warn!("serial {}: {}, waiting for it", setting.label, e);
+                    warned = true;
+                }
+                // feedback is only worth anything right away
+                writes.try_iter().for_each(drop);
+                if setting.auto_detect {
+                    setting.port_name = None;
+                }
+                thread::sleep(RECONNECT_INTERVAL);
+                continue;
+            }
+        };
+        warned = false;
+        setting.fallback = false;
+
+        if sender.send(SerialMessage::Connected(port_name)).is_err() {
+            return;
+        }
+
+        let mut last_data = Instant::now();
+        loop {
+            if writes.try_iter().any(|bytes| port.write_all(&bytes).is_err()) {
+                break;
+            }
+
+            match port.read(&mut buffer) {
+                Ok(0) => break,
+                Ok(n) => {
+                    last_data = Instant::now();
+                    if sender.send(SerialMessage::Data(buffer[..n].to_vec())).is_err() {
+                        return;
+                    }
+                },
+                Err(e) if e.kind() == ErrorKind::TimedOut || e.kind() == ErrorKind::WouldBlock => {
+                    if last_data.elapsed() > SILENCE_TIMEOUT {
+                        break;
+                    }
+                },
+                Err(e) if e.kind() == ErrorKind::Interrupted => {},
+                Err(_) => break,
+            }
+        }
+
+        drop(port);
+        if sender.send(SerialMessage::Disconnected).is_err() {
+            return;
+        }
+        thread::sleep(RECONNECT_INTERVAL);
+    }
+}
@@ -0,0 +1,120 @@
+use std::str::FromStr;
+
+use crate::*;
+
+// how hard a full tilt pushes the lean back, see `STEERING_TORQUE`
+pub const STEERING_RATE: f32 = 20.0;
+pub const RESPONSE_EXPONENT: f32 = 2.0;
+pub const RESPONSE_DEADZONE: f32 = 0.2;
+
+/// How `Movement` pushes back on the bike's lean.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Reflect)]
+pub enum SteeringMode {
+    /// a fixed correction whenever the handlebar is tilted
+    #[default]
+    Digital,
+    /// the harder the tilt, the stronger the correction
+    Proportional,
+}
+
+/// Shapes the tilt before it's used in proportional mode.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Reflect)]
+pub enum ResponseCurve {
+    #[default]
+    Linear,
+    /// gentle around the middle, strong at the ends
+    Exponential,
+    /// ignores small tilts, then linear up to a full tilt
+    Deadzone,
+}
+
+#[derive(Resource, Debug, Clone, Copy, PartialEq, Reflect)]
+#[reflect(Resource)]
+pub struct Steering {
+    pub mode: SteeringMode,
+    pub curve: ResponseCurve,
+    /// how hard a full tilt pushes the lean back
+    pub rate: f32,
+    pub exponent: f32,
+    pub deadzone: f32,
+}
+
+impl Default for Steering {
+    fn default() -> Self {
+        Self {
+            mode: SteeringMode::Digital,
+            curve: ResponseCurve::Linear,
+            rate: STEERING_RATE,
+            exponent: RESPONSE_EXPONENT,
+            deadzone: RESPONSE_DEADZONE,
+        }
+    }
+}
+
+impl FromStr for SteeringMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "digital" => Ok(Self::Digital),
+            "proportional" => Ok(Self::Proportional),
+            _ => Err(format!("unknown steering mode {:?}", s)),
+        }
+    }
+}
+
+impl FromStr for ResponseCurve {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "linear" => Ok(Self::Linear),
+            "exponential" => Ok(Self::Exponential),
+            "deadzone" => Ok(Self::Deadzone),
+            _ => Err(format!("unknown response curve {:?}", s)),
+        }
+    }
+}
+
+impl Steering {
+    /// Takes and returns values between -1.0 and 1.0.
+    pub fn response(&self, tilt: f32) -> f32 {
+        let tilt = tilt.clamp(-1.0, 1.0);
+        match self.curve {
+            ResponseCurve::Linear => tilt,
+            ResponseCurve::Exponential => tilt.abs().powf(self.exponent).copysign(tilt),
+            ResponseCurve::Deadzone => {
+                if tilt.abs() <= self.deadzone {
+                    0.0
+                } else {
+                    ((tilt.abs() - self.deadzone) / (1.0 - self.deadzone)).copysign(tilt)
+                }
+            },
+        }
+    }
+
+    /// How hard the rider pushes the lean back, -1.0 to 1.0.
+    pub fn correction(&self, movement: &Movement) -> f32 {
+        match self.mode {
+            SteeringMode::Digital => match movement.direction {
+                Dir::Left => 1.0,
+                Dir::Right => -1.0,
+                Dir::Neutral => 0.0,
+            },
+            SteeringMode::Proportional => self.response(movement.value),
+        }
+    }
+}
+
+pub fn toggle_steering_mode(
+    keys: Res<Input<KeyCode>>,
+    mut steering: ResMut<Steering>,
+) {
+    if keys.just_pressed(KeyCode::Tab) {
+        steering.mode = match steering.mode {
+            SteeringMode::Digital => SteeringMode::Proportional,
+            SteeringMode::Proportional => SteeringMode::Digital,
+        };
+        info!("steering is now {:?}", steering.mode);
+    }
+}
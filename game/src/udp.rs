@@ -0,0 +1,171 @@
+use std::{
+    io::ErrorKind,
+    net::{SocketAddr, UdpSocket},
+    sync::{mpsc, Mutex},
+    thread,
+    time::Instant,
+};
+
+use crate::*;
+
+// what the esp32 firmware sends to unless it's built with another port
+pub const DEFAULT_UDP_PORT: u16 = 4210;
+
+/// A datagram from the controller, one or more complete lines.
+#[derive(Event)]
+pub struct UdpReadEvent(pub SocketAddr, pub Vec<u8>);
+
+/// Bytes for the controller, sent to wherever it last sent from.
+/// Dropped before it has sent anything.
+#[derive(Event)]
+pub struct UdpWriteEvent(pub Vec<u8>);
+
+/// There's no connection to lose over udp, a controller counts as connected
+/// from its first valid frame and as gone after `SILENCE_TIMEOUT` without one.
+#[derive(Event, Debug, Clone, PartialEq)]
+pub enum UdpConnectionEvent {
+    Connected { peer: SocketAddr },
+    Disconnected,
+}
+
+/// Listens for a controller streaming over wi-fi on a background thread,
+/// the udp counterpart of `SerialPlugin`. Without a socket it does nothing.
+pub struct UdpPlugin {
+    pub socket: Option<UdpSocket>,
+}
+
+enum UdpMessage {
+    Data(SocketAddr, Vec<u8>),
+    Connected(SocketAddr),
+    Disconnected,
+}
+
+#[derive(Resource)]
+struct UdpChannel {
+    receiver: Mutex<mpsc::Receiver<UdpMessage>>,
+    sender: Mutex<mpsc::Sender<Vec<u8>>>,
+}
+
+impl Plugin for UdpPlugin {
+    fn build(&self, app: &mut App) {
+        app
+        .add_event::<UdpReadEvent>()
+        .add_event::<UdpWriteEvent>()
+        .add_event::<UdpConnectionEvent>()
+        ;
+
+        let Some(socket) = self.socket.as_ref().and_then(|socket| socket.try_clone().ok()) else {
+            return;
+        };
+        let (sender, receiver) = mpsc::channel();
+        let (write_sender, write_receiver) = mpsc::channel();
+        thread::Builder::new()
+            .name("udp controller".to_string())
+            .spawn(move || run_socket(socket, sender, write_receiver))
+            .expect("failed to spawn udp thread");
+
+        app
+        .insert_resource(UdpChannel {
+            receiver: Mutex::new(receiver),
+            sender: Mutex::new(write_sender),
+        })
+        .add_systems(PreUpdate, receive_udp)
+        .add_systems(PostUpdate, send_udp)
+        ;
+    }
+}
+
+fn receive_udp(
+    channel: Res<UdpChannel>,
+    mut read_events: EventWriter<UdpReadEvent>,
+    mut connection_events: EventWriter<UdpConnectionEvent>,
+) {
+    let receiver = channel.receiver.lock().unwrap();
+    for message in receiver.try_iter() {
+        match message {
+            UdpMessage::Data(peer, bytes) => read_events.send(UdpReadEvent(peer, bytes)),
+            UdpMessage::Connected(peer) => {
+                info!("udp controller connected from {}", peer);
+                connection_events.send(UdpConnectionEvent::Connected { peer });
+            },
+            UdpMessage::Disconnected => {
+                warn!("udp controller went quiet");
+                connection_events.send(UdpConnectionEvent::Disconnected);
+            },
+        }
+    }
+}
+
+fn send_udp(
+    channel: Res<UdpChannel>,
+    mut write_events: EventReader<UdpWriteEvent>,
+) {
+    for UdpWriteEvent(bytes) in write_events.iter() {
+        // only fails once the thread is gone, and then nothing's listening anyway
+        let _ = channel.sender.lock().unwrap().send(bytes.clone());
+    }
+}
+
+// runs until the game goes away and drops the receiver
+fn run_socket(
+    socket: UdpSocket,
+    sender: mpsc::Sender<UdpMessage>,
+    writes: mpsc::Receiver<Vec<u8>>,
+) {
+    if let Err(e) = socket.set_read_timeout(Some(READ_TIMEOUT)) {
+        warn!("could not set a timeout on the udp socket: {}", e);
+        return;
+    }
+
+    // a whole frame always fits, and several usually do
+    let mut buffer = [0u8; 1500];
+    let mut peer: Option<SocketAddr> = None;
+    let mut last_data = Instant::now();
+
+    loop {
+        for bytes in writes.try_iter() {
+            if let Some(peer) = peer {
+                // a lost command is no worse than a late one
+                let _ = socket.send_to(&bytes, peer);
+            }
+        }
+
+        match socket.recv_from(&mut buffer) {
+            Ok((n, from)) => {
+                // anything can land on the port, only the controller's frames count,
+                // the rest still goes through to show up as parse errors
+                if from_controller(&buffer[..n]) {
+                    last_data = Instant::now();
+                    if peer != Some(from) {
+                        peer = Some(from);
+                        if sender.send(UdpMessage::Connected(from)).is_err() {
+                            return;
+                        }
+                    }
+                }
+                if sender.send(UdpMessage::Data(from, buffer[..n].to_vec())).is_err() {
+                    return;
+                }
+            },
+            Err(e) if e.kind() == ErrorKind::TimedOut || e.kind() == ErrorKind::WouldBlock => {
+                if peer.is_some() && last_data.elapsed() > SILENCE_TIMEOUT {
+                    peer = None;
+                    if sender.send(UdpMessage::Disconnected).is_err() {
+                        return;
+                    }
+                }
+            },
+            // windows reports a peer that went away as an error on the next read,
+            // anything else shouldn't spin
+            Err(_) => thread::sleep(READ_TIMEOUT),
+        }
+    }
+}
+
+// a datagram holds whole lines, one reading or hello is enough
+fn from_controller(bytes: &[u8]) -> bool {
+    let Ok(text) = std::str::from_utf8(bytes) else {
+        return false;
+    };
+    text.lines().any(|line| matches!(parse_frame(line.trim()), Ok(Frame::Sample(_) | Frame::Hello(_))))
+}